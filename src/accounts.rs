@@ -0,0 +1,418 @@
+use std::sync::Arc;
+
+use tokio::sync::{mpsc, Mutex};
+
+use crate::{
+    app::{Task, Tasklist},
+    provider::{Conflict, Conflicts, Loader, Provider},
+};
+
+/// Shows the tasklists of several accounts together.
+///
+/// Tasklist ids are prefixed with the account name (`work/<id>`) so every call can be routed to
+/// the provider of the account the tasklist belongs to. Task ids are passed through unchanged.
+pub struct Accounts {
+    accounts: Vec<(String, Box<dyn Provider>)>,
+
+    tasklists: Vec<Tasklist>,
+}
+
+impl Accounts {
+    pub fn new(accounts: Vec<(String, Box<dyn Provider>)>) -> Self {
+        let mut accounts = Self {
+            accounts,
+            tasklists: Vec::new(),
+        };
+        accounts.collect();
+
+        accounts
+    }
+
+    /// Rebuilds the combined tasklists, grouped by account.
+    fn collect(&mut self) {
+        self.tasklists = self
+            .accounts
+            .iter()
+            .flat_map(|(name, provider)| scope(name, provider.get_tasklists().clone()))
+            .collect();
+    }
+
+    /// Finds the provider of a prefixed tasklist id, returning it with the unprefixed id.
+    fn route<'a>(
+        &mut self,
+        tasklist_id: &'a str,
+    ) -> anyhow::Result<(&mut Box<dyn Provider>, &'a str)> {
+        let (name, id) = tasklist_id.split_once('/').ok_or(anyhow::anyhow!(
+            "tasklist with id {} not found",
+            tasklist_id
+        ))?;
+        let (_, provider) = self
+            .accounts
+            .iter_mut()
+            .find(|(n, _)| n == name)
+            .ok_or(anyhow::anyhow!("account {} not found", name))?;
+
+        Ok((provider, id))
+    }
+
+    /// Refreshes the combined tasklists after a call routed to the account of `tasklist_id`,
+    /// prefixing the tasklist id of a conflict it ran into.
+    fn finish<T>(&mut self, tasklist_id: &str, result: anyhow::Result<T>) -> anyhow::Result<T> {
+        self.collect();
+
+        let name = tasklist_id.split_once('/').map_or("", |(name, _)| name);
+        result.map_err(|err| match err.downcast::<Conflict>() {
+            Ok(conflict) => scope_conflict(name, conflict).into(),
+            Err(err) => err,
+        })
+    }
+}
+
+fn scope_conflict(name: &str, conflict: Conflict) -> Conflict {
+    Conflict {
+        tasklist_id: format!("{}/{}", name, conflict.tasklist_id),
+        ..conflict
+    }
+}
+
+fn scope(name: &str, tasklists: Vec<Tasklist>) -> Vec<Tasklist> {
+    tasklists
+        .into_iter()
+        .map(|t| Tasklist {
+            id: format!("{}/{}", name, t.id),
+            ..t
+        })
+        .collect()
+}
+
+/// Scopes the tasklists an account loaded, followed by an empty marker tasklist with id
+/// `<name>/` so `set_tasklists` knows the account was loaded even when it has no tasklists.
+fn loaded_account(name: &str, tasklists: Vec<Tasklist>) -> Vec<Tasklist> {
+    let mut tasklists = scope(name, tasklists);
+    tasklists.push(Tasklist {
+        id: format!("{}/", name),
+        title: String::new(),
+        tasks: Vec::new(),
+    });
+
+    tasklists
+}
+
+#[async_trait::async_trait]
+impl Provider for Accounts {
+    fn get_tasklists(&self) -> &Vec<Tasklist> {
+        &self.tasklists
+    }
+
+    fn account(&self, tasklist_id: &str) -> Option<&str> {
+        let (name, _) = tasklist_id.split_once('/')?;
+
+        self.accounts
+            .iter()
+            .map(|(n, _)| n.as_str())
+            .find(|n| *n == name)
+    }
+
+    async fn sync(&mut self) -> anyhow::Result<()> {
+        // One account failing should not hide the others, the first error is still reported.
+        // Conflicts of every account are, as no one else keeps them.
+        let mut result = Ok(());
+        let mut conflicts = Vec::new();
+        for (name, provider) in self.accounts.iter_mut() {
+            if let Err(err) = provider.sync().await {
+                match err.downcast::<Conflicts>() {
+                    Ok(Conflicts(c)) => {
+                        conflicts.extend(c.into_iter().map(|c| scope_conflict(name, c)))
+                    }
+                    Err(err) if result.is_ok() => result = Err(err),
+                    Err(_) => {}
+                }
+            }
+        }
+        self.collect();
+
+        match conflicts.is_empty() {
+            true => result,
+            false => Err(Conflicts(conflicts).into()),
+        }
+    }
+
+    fn is_offline_error(&self, err: &anyhow::Error) -> bool {
+        self.accounts.iter().any(|(_, p)| p.is_offline_error(err))
+    }
+
+    fn pending_changes(&self) -> usize {
+        self.accounts.iter().map(|(_, p)| p.pending_changes()).sum()
+    }
+
+    fn loader(&self) -> Option<Loader> {
+        let loaders = Arc::new(
+            self.accounts
+                .iter()
+                .filter_map(|(name, p)| p.loader().map(|l| (name.clone(), l)))
+                .collect::<Vec<_>>(),
+        );
+        if loaders.is_empty() {
+            return None;
+        }
+
+        Some(Box::new(move || {
+            let loaders = loaders.clone();
+            Box::pin(async move {
+                // An unreachable account keeps its tasklists, only when all of them fail the
+                // refresh does.
+                let mut tasklists = Vec::new();
+                let mut error = None;
+                for (name, loader) in loaders.iter() {
+                    match loader().await {
+                        Ok(loaded) => tasklists.extend(loaded_account(name, loaded)),
+                        Err(err) => {
+                            error.get_or_insert(err);
+                        }
+                    }
+                }
+
+                match error {
+                    Some(err) if tasklists.is_empty() => Err(err),
+                    _ => Ok(tasklists),
+                }
+            })
+        }))
+    }
+
+    fn watcher(&self) -> Option<Loader> {
+        let watchers = self
+            .accounts
+            .iter()
+            .filter_map(|(name, p)| p.watcher().map(|w| (name.clone(), w)))
+            .collect::<Vec<_>>();
+        if watchers.is_empty() {
+            return None;
+        }
+
+        // Every watcher waits on its own, whichever resolves first is handed out.
+        let (tx, rx) = mpsc::unbounded_channel();
+        for (name, watcher) in watchers {
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                while tx
+                    .send(watcher().await.map(|t| loaded_account(&name, t)))
+                    .is_ok()
+                {}
+            });
+        }
+        let rx = Arc::new(Mutex::new(rx));
+
+        Some(Box::new(move || {
+            let rx = rx.clone();
+            Box::pin(async move {
+                match rx.lock().await.recv().await {
+                    Some(tasklists) => tasklists,
+                    None => std::future::pending().await,
+                }
+            })
+        }))
+    }
+
+    /// Only replaces the tasklists of accounts that were loaded, which are marked by the empty
+    /// `<name>/` tasklist, as loaders and watchers leave out accounts they could not load.
+    fn set_tasklists(&mut self, tasklists: Vec<Tasklist>) {
+        for (name, provider) in self.accounts.iter_mut() {
+            let prefix = format!("{}/", name);
+            if !tasklists.iter().any(|t| t.id == prefix) {
+                continue;
+            }
+
+            let own = tasklists
+                .iter()
+                .filter_map(|t| {
+                    t.id.strip_prefix(&prefix)
+                        .filter(|id| !id.is_empty())
+                        .map(|id| Tasklist {
+                            id: id.to_string(),
+                            ..t.clone()
+                        })
+                })
+                .collect::<Vec<_>>();
+            provider.set_tasklists(own);
+        }
+        self.collect();
+    }
+
+    /// Creates the tasklist in the first account.
+    async fn create_tasklist(&mut self, title: &str) -> anyhow::Result<String> {
+        self.create_tasklist_in(None, title).await
+    }
+
+    async fn create_tasklist_in(
+        &mut self,
+        account: Option<&str>,
+        title: &str,
+    ) -> anyhow::Result<String> {
+        let (name, provider) = match account {
+            Some(account) => self
+                .accounts
+                .iter_mut()
+                .find(|(n, _)| n == account)
+                .ok_or(anyhow::anyhow!("account {} not found", account))?,
+            None => self
+                .accounts
+                .first_mut()
+                .ok_or(anyhow::anyhow!("no accounts"))?,
+        };
+
+        let id = format!("{}/{}", name, provider.create_tasklist(title).await?);
+        self.collect();
+
+        Ok(id)
+    }
+
+    async fn rename_tasklist(&mut self, tasklist_id: &str, title: &str) -> anyhow::Result<()> {
+        let (provider, id) = self.route(tasklist_id)?;
+        let result = provider.rename_tasklist(id, title).await;
+
+        self.finish(tasklist_id, result)
+    }
+
+    async fn delete_tasklist(&mut self, tasklist_id: &str) -> anyhow::Result<()> {
+        let (provider, id) = self.route(tasklist_id)?;
+        let result = provider.delete_tasklist(id).await;
+
+        self.finish(tasklist_id, result)
+    }
+
+    async fn update_task(&mut self, tasklist_id: &str, task: &Task) -> anyhow::Result<()> {
+        let (provider, id) = self.route(tasklist_id)?;
+        let result = provider.update_task(id, task).await;
+
+        self.finish(tasklist_id, result)
+    }
+
+    async fn create_task(&mut self, tasklist_id: &str, task: &Task) -> anyhow::Result<String> {
+        let (provider, id) = self.route(tasklist_id)?;
+        let result = provider.create_task(id, task).await;
+
+        self.finish(tasklist_id, result)
+    }
+
+    async fn delete_task(&mut self, tasklist_id: &str, task_id: &str) -> anyhow::Result<()> {
+        let (provider, id) = self.route(tasklist_id)?;
+        let result = provider.delete_task(id, task_id).await;
+
+        self.finish(tasklist_id, result)
+    }
+
+    async fn move_task(
+        &mut self,
+        tasklist_id: &str,
+        task_id: &str,
+        parent: Option<&str>,
+        previous: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let (provider, id) = self.route(tasklist_id)?;
+        let result = provider.move_task(id, task_id, parent, previous).await;
+
+        self.finish(tasklist_id, result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Keeps its tasklists, loading returns `loaded`.
+    struct Stub {
+        tasklists: Vec<Tasklist>,
+        loaded: Option<Vec<Tasklist>>,
+    }
+
+    #[async_trait::async_trait]
+    impl Provider for Stub {
+        fn get_tasklists(&self) -> &Vec<Tasklist> {
+            &self.tasklists
+        }
+
+        async fn sync(&mut self) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        fn loader(&self) -> Option<Loader> {
+            let loaded = self.loaded.clone();
+            Some(Box::new(move || {
+                let loaded = loaded.clone();
+                Box::pin(async move { loaded.ok_or(anyhow::anyhow!("unreachable")) })
+            }))
+        }
+
+        fn set_tasklists(&mut self, tasklists: Vec<Tasklist>) {
+            self.tasklists = tasklists;
+        }
+
+        async fn create_tasklist(&mut self, _title: &str) -> anyhow::Result<String> {
+            unimplemented!()
+        }
+        async fn rename_tasklist(
+            &mut self,
+            _tasklist_id: &str,
+            _title: &str,
+        ) -> anyhow::Result<()> {
+            unimplemented!()
+        }
+        async fn delete_tasklist(&mut self, _tasklist_id: &str) -> anyhow::Result<()> {
+            unimplemented!()
+        }
+        async fn update_task(&mut self, _tasklist_id: &str, _task: &Task) -> anyhow::Result<()> {
+            unimplemented!()
+        }
+        async fn create_task(
+            &mut self,
+            _tasklist_id: &str,
+            _task: &Task,
+        ) -> anyhow::Result<String> {
+            unimplemented!()
+        }
+        async fn delete_task(&mut self, _tasklist_id: &str, _task_id: &str) -> anyhow::Result<()> {
+            unimplemented!()
+        }
+        async fn move_task(
+            &mut self,
+            _tasklist_id: &str,
+            _task_id: &str,
+            _parent: Option<&str>,
+            _previous: Option<&str>,
+        ) -> anyhow::Result<()> {
+            unimplemented!()
+        }
+    }
+
+    fn stub(loaded: Option<Vec<Tasklist>>) -> Box<dyn Provider> {
+        Box::new(Stub {
+            tasklists: vec![Tasklist::new("1".to_string(), "Inbox".to_string(), &[])],
+            loaded,
+        })
+    }
+
+    #[test]
+    fn refresh_clears_loaded_accounts_without_tasklists() {
+        let mut accounts = Accounts::new(vec![
+            ("home".to_string(), stub(Some(Vec::new()))),
+            ("work".to_string(), stub(None)),
+        ]);
+
+        let loader = accounts.loader().unwrap();
+        let tasklists = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(loader())
+            .unwrap();
+        accounts.set_tasklists(tasklists);
+
+        // Home has no tasklists left, work could not be loaded and keeps its own.
+        let ids = accounts
+            .get_tasklists()
+            .iter()
+            .map(|t| t.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["work/1"]);
+    }
+}
@@ -1,33 +1,151 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
 use tui::widgets::TableState;
 
-use crate::{provider::Provider, timestamps::TimestampType};
+use crate::{
+    config::{self, Cfg, Filter, Sort, TasklistCfg},
+    form::{Input, Search, TaskForm, TasklistForm, TasklistPicker},
+    fuzzy,
+    notifications::Notifications,
+    provider::{Conflict, Conflicts},
+    timestamps::TimestampType,
+    worker::{Done, Job, Worker},
+};
+
+#[derive(Debug)]
+pub enum Mode {
+    Normal,
+    TaskForm(TaskForm),
+    /// Task to delete, with the id of its tasklist.
+    ConfirmDelete(String, Task),
+    TasklistForm(TasklistForm),
+    ConfirmDeleteTasklist(Tasklist),
+    MoveToList(TasklistPicker),
+    Conflict(Conflict),
+    Search(Search),
+    /// Log of the warnings and errors, scrolled down by the given lines.
+    ErrorLog(u16),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Move {
+    Up,
+    Down,
+    Indent,
+    Outdent,
+}
+
+/// Virtual tasklist made of the open tasks of every tasklist, pinned before them in the tabs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum View {
+    /// Due today or overdue.
+    Today,
+    /// Due within the next `upcoming_days` days.
+    Upcoming,
+}
+impl View {
+    pub const ALL: [View; 2] = [View::Today, View::Upcoming];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            View::Today => "Today",
+            View::Upcoming => "Upcoming",
+        }
+    }
+
+    /// Due date filled into the form of tasks added from the view.
+    fn default_due(self) -> &'static str {
+        match self {
+            View::Today => "today",
+            View::Upcoming => "tomorrow",
+        }
+    }
+}
+
+const UNDO_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+pub struct DeletedTask {
+    pub tasklist_id: String,
+    pub task: Task,
+    /// The task and its subtasks, parents first, each with the sibling it came after.
+    pub subtree: Vec<(Task, Option<String>)>,
+    pub deleted_at: Instant,
+}
 
 pub struct App {
     pub should_quit: bool,
+    pub mode: Mode,
 
-    pub provider: Box<dyn Provider>,
+    pub provider: Worker,
+    /// Selected tab, the views come first and are followed by the tasklists.
     pub active_tasklist: usize,
+    pub upcoming_days: u64,
+    /// Title or id of the tasklist tasks added in a view go to.
+    pub default_tasklist: Option<String>,
+    /// View settings by tasklist id, tasklists without any use the defaults.
+    pub tasklists: HashMap<String, TasklistCfg>,
     pub tasks_state: TableState,
+    pub collapsed: HashSet<String>,
+
+    pub deleted: Option<DeletedTask>,
+    /// Forms by the job saving them, reopened when it fails.
+    pub submitted: HashMap<u64, TaskForm>,
+    /// Conflicts waiting for the prompt.
+    pub conflicts: VecDeque<Conflict>,
+    pub notifications: Notifications,
 }
 impl App {
-    pub fn new(provider: impl Provider + 'static) -> Self {
+    pub fn new(provider: Worker, cfg: &Cfg) -> Self {
         Self {
             should_quit: false,
-            provider: Box::new(provider),
-            active_tasklist: 0,
+            mode: Mode::Normal,
+            active_tasklist: match provider.is_empty() {
+                true => 0,
+                false => View::ALL.len(),
+            },
+            provider,
+            upcoming_days: cfg.upcoming_days,
+            default_tasklist: cfg.default_tasklist.clone(),
+            tasklists: cfg.tasklists.clone(),
             tasks_state: TableState::default(),
+            collapsed: HashSet::new(),
+            deleted: None,
+            submitted: HashMap::new(),
+            conflicts: VecDeque::new(),
+            notifications: Notifications::default(),
         }
     }
 
-    pub fn on_tick(&mut self) {}
+    pub fn on_tick(&mut self) {
+        if self
+            .deleted
+            .as_ref()
+            .is_some_and(|d| d.deleted_at.elapsed() >= UNDO_TIMEOUT)
+        {
+            self.deleted = None;
+        }
+        self.next_conflict();
+    }
     pub fn quit(&mut self) {
         self.should_quit = true;
     }
+    pub fn cancel(&mut self) {
+        self.mode = Mode::Normal;
+    }
+
+    /// Number of tabs, views included.
+    pub fn tabs_len(&self) -> usize {
+        View::ALL.len() + self.provider.len()
+    }
 
     pub fn tasklists_next(&mut self) {
         self.tasks_state = TableState::default();
-
-        self.active_tasklist = (self.active_tasklist + 1) % self.provider.len();
+        self.active_tasklist = (self.active_tasklist + 1) % self.tabs_len();
     }
     pub fn tasklists_previous(&mut self) {
         self.tasks_state = TableState::default();
@@ -35,19 +153,320 @@ impl App {
         if self.active_tasklist > 0 {
             self.active_tasklist -= 1;
         } else {
-            self.active_tasklist = self.provider.len() - 1;
+            self.active_tasklist = self.tabs_len() - 1;
+        }
+    }
+
+    pub fn sync(&mut self) -> anyhow::Result<()> {
+        self.provider.send(Job::Sync)?;
+
+        Ok(())
+    }
+
+    /// Hands tasklists loaded in the background to the provider.
+    pub fn refresh(&mut self, started: u64, tasklists: anyhow::Result<Vec<Tasklist>>) {
+        self.provider.refresh(started, tasklists);
+    }
+
+    /// Applies the result of a provider call, a failed one is rolled back and reported.
+    ///
+    /// Being offline is only reported for syncs, pending changes show it otherwise.
+    pub fn finish(&mut self, mut done: Done) {
+        let (tasklist_id, task_id) = self.selection();
+        let job = self.provider.finish(&mut done);
+        let form = done.id.and_then(|id| self.submitted.remove(&id));
+        self.restore_selection((
+            tasklist_id.map(|id| self.provider.resolve(&id)),
+            task_id.map(|id| self.provider.resolve(&id)),
+        ));
+        self.resolve_tasklist_cfgs();
+
+        let err = match (done.result, job) {
+            (Ok(()), Some(Job::Sync)) => return self.notifications.info("Synced"),
+            (Ok(()), _) => return,
+            (Err(_), Some(Job::Sync)) if done.offline => {
+                return self
+                    .notifications
+                    .warning("Offline, showing the tasks from the last sync")
+            }
+            (Err(_), None) if done.offline => return,
+            (Err(err), None) => err.context("background refresh failed"),
+            (Err(err), Some(job)) => {
+                if let Job::DeleteTask { task, .. } = &job {
+                    if self.deleted.as_ref().is_some_and(|d| d.task.id == task.id) {
+                        self.deleted = None;
+                    }
+                }
+
+                let err = match err.downcast::<Conflict>() {
+                    Ok(conflict) => return self.add_conflicts(vec![conflict]),
+                    Err(err) => match err.downcast::<Conflicts>() {
+                        Ok(Conflicts(conflicts)) => return self.add_conflicts(conflicts),
+                        Err(err) => err,
+                    },
+                };
+                if let Some(mut form) = form.filter(|_| matches!(self.mode, Mode::Normal)) {
+                    form.tasklist_id = self.provider.resolve(&form.tasklist_id);
+                    if let Some(task) = &mut form.task {
+                        task.id = self.provider.resolve(&task.id);
+                    }
+                    self.mode = Mode::TaskForm(form);
+                }
+
+                err.context(format!("could not {}", job))
+            }
+        };
+
+        self.notifications.error(&err);
+    }
+
+    /// Queues conflicts for the prompt, which shows them one by one whenever nothing else is open.
+    fn add_conflicts(&mut self, conflicts: Vec<Conflict>) {
+        self.conflicts.extend(conflicts);
+        self.next_conflict();
+    }
+    fn next_conflict(&mut self) {
+        if matches!(self.mode, Mode::Normal) {
+            if let Some(conflict) = self.conflicts.pop_front() {
+                self.mode = Mode::Conflict(conflict);
+            }
+        }
+    }
+
+    pub fn open_error_log(&mut self) {
+        self.notifications.dismiss();
+        self.mode = Mode::ErrorLog(0);
+    }
+    pub fn scroll_error_log(&mut self, down: bool) {
+        if let Mode::ErrorLog(scroll) = &mut self.mode {
+            *scroll = match down {
+                true => scroll.saturating_add(1),
+                false => scroll.saturating_sub(1),
+            };
+        }
+    }
+
+    fn selection(&self) -> (Option<String>, Option<String>) {
+        (
+            self.active_tasklist().map(|t| t.id.clone()),
+            self.active_task().map(|t| t.id.clone()),
+        )
+    }
+    /// Selects the same tasklist and task after the tasklists were reloaded, views stay in place.
+    fn restore_selection(&mut self, (tasklist_id, task_id): (Option<String>, Option<String>)) {
+        let active_tasklist = tasklist_id.and_then(|id| {
+            self.provider
+                .get_tasklists()
+                .iter()
+                .position(|t| t.id == id)
+                .map(|i| i + View::ALL.len())
+        });
+        match active_tasklist {
+            Some(i) if i != self.active_tasklist => {
+                self.active_tasklist = i;
+                self.tasks_state = TableState::default();
+            }
+            _ => self.active_tasklist = self.active_tasklist.min(self.tabs_len() - 1),
+        }
+
+        match task_id {
+            Some(id) if self.visible_tasks().iter().any(|row| row.task.id == id) => {
+                self.select_task(&id)
+            }
+            _ => self.clamp_task_selection(),
         }
     }
 
+    pub fn active_view(&self) -> Option<View> {
+        View::ALL.get(self.active_tasklist).copied()
+    }
+    /// Selected tasklist, `None` while a view is selected.
     pub fn active_tasklist(&self) -> Option<&Tasklist> {
-        self.provider.get_nth_tasklist(self.active_tasklist)
+        self.active_tasklist
+            .checked_sub(View::ALL.len())
+            .and_then(|i| self.provider.get_nth_tasklist(i))
     }
 
-    pub fn tasks_next(&mut self) {
+    /// View settings of the selected tasklist.
+    pub fn tasklist_cfg(&self) -> TasklistCfg {
+        self.active_tasklist()
+            .and_then(|t| self.tasklists.get(&self.provider.resolve(&t.id)))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Changes the view settings of the selected tasklist and saves them to the cache dir.
+    fn update_tasklist_cfg(&mut self, f: impl FnOnce(&mut TasklistCfg)) -> anyhow::Result<()> {
+        let tasklist_id = match self.active_tasklist() {
+            Some(tasklist) => self.provider.resolve(&tasklist.id),
+            None => return Ok(()),
+        };
+        let selection = self.selection();
+
+        let mut tasklist = self.tasklist_cfg();
+        f(&mut tasklist);
+        self.tasklists.insert(tasklist_id, tasklist);
+        config::save_tasklist_cfgs(&self.tasklists)?;
+
+        self.restore_selection(selection);
+
+        Ok(())
+    }
+    /// Moves the view settings of tasklists saved while they were still being created to their
+    /// real ids.
+    fn resolve_tasklist_cfgs(&mut self) {
+        let created = self
+            .tasklists
+            .keys()
+            .filter(|id| self.provider.resolve(id) != **id)
+            .cloned()
+            .collect::<Vec<_>>();
+        if created.is_empty() {
+            return;
+        }
+
+        for id in created {
+            if let Some(tasklist) = self.tasklists.remove(&id) {
+                self.tasklists.insert(self.provider.resolve(&id), tasklist);
+            }
+        }
+        if let Err(err) = config::save_tasklist_cfgs(&self.tasklists) {
+            self.notifications.error(&err);
+        }
+    }
+    pub fn toggle_completed(&mut self) -> anyhow::Result<()> {
+        self.update_tasklist_cfg(|t| t.show_completed = !t.show_completed)
+    }
+    pub fn next_sort(&mut self) -> anyhow::Result<()> {
+        self.update_tasklist_cfg(|t| t.sort = config::next(&t.sort))
+    }
+    pub fn next_filter(&mut self) -> anyhow::Result<()> {
+        self.update_tasklist_cfg(|t| t.filter = config::next(&t.filter))
+    }
+
+    pub fn open_tasklist_form(&mut self) {
+        self.mode = Mode::TasklistForm(TasklistForm::default());
+    }
+    pub fn open_rename_tasklist_form(&mut self) {
+        if let Some(tasklist) = self.active_tasklist() {
+            self.mode = Mode::TasklistForm(TasklistForm::rename(tasklist));
+        }
+    }
+    pub fn tasklist_form(&mut self) -> Option<&mut TasklistForm> {
+        match &mut self.mode {
+            Mode::TasklistForm(form) => Some(form),
+            _ => None,
+        }
+    }
+
+    pub fn submit_tasklist_form(&mut self) -> anyhow::Result<()> {
+        let (title, tasklist_id) = match &self.mode {
+            Mode::TasklistForm(form) => (form.to_title()?, form.tasklist_id.clone()),
+            _ => return Ok(()),
+        };
+
+        match tasklist_id {
+            Some(tasklist_id) => {
+                self.provider
+                    .send(Job::RenameTasklist { tasklist_id, title })?;
+            }
+            None => {
+                let id = self.provider.new_id();
+
+                // New tasklists go to the account of the one being looked at.
+                let account = self
+                    .active_tasklist()
+                    .and_then(|t| self.provider.account(&t.id))
+                    .map(|account| account.to_string());
+                self.provider.send(Job::CreateTasklist {
+                    id: id.clone(),
+                    account,
+                    title,
+                })?;
+
+                self.restore_selection((Some(id), None));
+            }
+        }
+        self.mode = Mode::Normal;
+
+        Ok(())
+    }
+
+    pub fn confirm_delete_tasklist(&mut self) {
         if let Some(tasklist) = self.active_tasklist() {
+            self.mode = Mode::ConfirmDeleteTasklist(tasklist.clone());
+        }
+    }
+
+    pub fn delete_tasklist(&mut self) -> anyhow::Result<()> {
+        let tasklist = match std::mem::replace(&mut self.mode, Mode::Normal) {
+            Mode::ConfirmDeleteTasklist(tasklist) => tasklist,
+            mode => {
+                self.mode = mode;
+                return Ok(());
+            }
+        };
+
+        self.provider.send(Job::DeleteTasklist {
+            tasklist_id: tasklist.id,
+            title: tasklist.title,
+        })?;
+
+        self.active_tasklist = self.active_tasklist.min(self.tabs_len() - 1);
+        self.tasks_state = TableState::default();
+
+        Ok(())
+    }
+
+    pub fn visible_tasks(&self) -> Vec<TaskRow<'_>> {
+        if let Some(view) = self.active_view() {
+            return self.view_tasks(view);
+        }
+
+        self.active_tasklist()
+            .map(|tasklist| tasklist.tree(&self.collapsed, &self.tasklist_cfg()))
+            .unwrap_or_default()
+    }
+
+    /// Open tasks of all tasklists that belong in the view, sorted by due date.
+    fn view_tasks(&self, view: View) -> Vec<TaskRow<'_>> {
+        let today = chrono::Local::now().date_naive();
+        let last = today
+            .checked_add_days(chrono::Days::new(self.upcoming_days))
+            .unwrap_or(today);
+
+        let mut rows = self
+            .provider
+            .get_tasklists()
+            .iter()
+            .flat_map(|tasklist| {
+                tasklist.tasks.iter().map(move |task| TaskRow {
+                    task,
+                    tasklist,
+                    depth: 0,
+                    has_children: false,
+                    collapsed: false,
+                })
+            })
+            .filter(|row| !matches!(row.task.status, Status::Done))
+            .filter(|row| {
+                row.task.due.as_ref().is_some_and(|due| match view {
+                    View::Today => due.date() <= today,
+                    View::Upcoming => due.date() > today && due.date() <= last,
+                })
+            })
+            .collect::<Vec<_>>();
+        rows.sort_by(|a, b| a.task.due.cmp(&b.task.due));
+
+        rows
+    }
+
+    pub fn tasks_next(&mut self) {
+        let len = self.visible_tasks().len();
+        if len > 0 {
             let i = match self.tasks_state.selected() {
                 Some(i) => {
-                    if i >= tasklist.len() - 1 {
+                    if i >= len - 1 {
                         0
                     } else {
                         i + 1
@@ -62,11 +481,12 @@ impl App {
         }
     }
     pub fn tasks_previous(&mut self) {
-        if let Some(tasklist) = self.active_tasklist() {
+        let len = self.visible_tasks().len();
+        if len > 0 {
             let i = match self.tasks_state.selected() {
                 Some(i) => {
                     if i == 0 {
-                        tasklist.len() - 1
+                        len - 1
                     } else {
                         i - 1
                     }
@@ -80,19 +500,48 @@ impl App {
         }
     }
 
-    fn active_task(&self) -> Option<&Task> {
-        if let Some(tasklist) = self.active_tasklist() {
-            if let Some(i) = self.tasks_state.selected() {
-                tasklist.get(i)
-            } else {
-                None
-            }
-        } else {
-            None
+    pub fn tasks_first(&mut self) {
+        let len = self.visible_tasks().len();
+        self.tasks_state.select(Some(0).filter(|_| len > 0));
+    }
+    pub fn tasks_last(&mut self) {
+        let len = self.visible_tasks().len();
+        self.tasks_state.select(len.checked_sub(1));
+    }
+
+    fn clamp_task_selection(&mut self) {
+        let len = self.visible_tasks().len();
+
+        match self.tasks_state.selected() {
+            Some(_) if len == 0 => self.tasks_state.select(None),
+            Some(i) if i >= len => self.tasks_state.select(Some(len - 1)),
+            _ => {}
         }
     }
 
-    pub async fn toggle_task_state(&mut self) -> anyhow::Result<()> {
+    fn active_row(&self) -> Option<TaskRow<'_>> {
+        let i = self.tasks_state.selected()?;
+
+        self.visible_tasks().get(i).copied()
+    }
+    fn active_task(&self) -> Option<&Task> {
+        self.active_row().map(|row| row.task)
+    }
+
+    fn select_task(&mut self, task_id: &str) {
+        let i = self
+            .visible_tasks()
+            .iter()
+            .position(|row| row.task.id == task_id);
+
+        self.tasks_state.select(i);
+    }
+
+    pub fn move_task(&mut self, direction: Move) -> anyhow::Result<()> {
+        // Tasks can only be moved where the table shows them in their own order.
+        if self.active_view().is_some() || self.tasklist_cfg().sort != Sort::Position {
+            return Ok(());
+        }
         let tasklist = self
             .active_tasklist()
             .ok_or(anyhow::anyhow!("no active tasklist"))?;
@@ -100,21 +549,421 @@ impl App {
             .active_task()
             .ok_or(anyhow::anyhow!("no active task"))?;
 
+        let siblings = tasklist.children(task.parent.as_deref());
+        let i = siblings
+            .iter()
+            .position(|t| t.id == task.id)
+            .ok_or(anyhow::anyhow!("task {} not found", task.id))?;
+        let id = |t: &&Task| t.id.clone();
+
+        let (parent, previous) = match direction {
+            Move::Up if i > 0 => (
+                task.parent.clone(),
+                i.checked_sub(2).and_then(|i| siblings.get(i)).map(id),
+            ),
+            Move::Down if i + 1 < siblings.len() => {
+                (task.parent.clone(), siblings.get(i + 1).map(id))
+            }
+            Move::Indent if i > 0 => {
+                let parent = siblings[i - 1];
+                (
+                    Some(parent.id.clone()),
+                    tasklist.children(Some(&parent.id)).last().map(id),
+                )
+            }
+            Move::Outdent => match task.parent.as_deref() {
+                Some(parent) => (
+                    tasklist
+                        .tasks
+                        .iter()
+                        .find(|t| t.id == parent)
+                        .and_then(|t| t.parent.clone()),
+                    Some(parent.to_string()),
+                ),
+                None => return Ok(()),
+            },
+            _ => return Ok(()),
+        };
+
+        let tasklist_id = tasklist.id.clone();
+        let task = task.clone();
+        let task_id = task.id.clone();
+
+        if let Some(parent) = &parent {
+            self.collapsed.remove(parent);
+        }
+        self.provider.send(Job::MoveTask {
+            tasklist_id,
+            task,
+            parent,
+            previous,
+        })?;
+        self.select_task(&task_id);
+
+        Ok(())
+    }
+
+    pub fn open_search(&mut self) {
+        self.mode = Mode::Search(Search::default());
+    }
+    pub fn search(&mut self) -> Option<&mut Search> {
+        match &mut self.mode {
+            Mode::Search(search) => Some(search),
+            _ => None,
+        }
+    }
+    pub fn search_next(&mut self) {
+        let len = self.search_results().len();
+        if let Some(search) = self.search() {
+            search.next(len);
+        }
+    }
+    pub fn search_previous(&mut self) {
+        let len = self.search_results().len();
+        if let Some(search) = self.search() {
+            search.previous(len);
+        }
+    }
+
+    /// Tasks of all tasklists matching the search query, best matches first.
+    ///
+    /// A match in the title counts more than one in the notes.
+    pub fn search_results(&self) -> Vec<(&Tasklist, &Task)> {
+        let query = match &self.mode {
+            Mode::Search(search) => search.query.value(),
+            _ => return Vec::new(),
+        };
+
+        let mut results = self
+            .provider
+            .get_tasklists()
+            .iter()
+            .flat_map(|tasklist| tasklist.tasks.iter().map(move |task| (tasklist, task)))
+            .filter_map(|(tasklist, task)| {
+                let title = fuzzy::score(query, &task.title).map(|s| s + 20);
+                let notes = task
+                    .notes
+                    .as_deref()
+                    .and_then(|notes| fuzzy::score(query, notes));
+
+                title.max(notes).map(|score| (score, tasklist, task))
+            })
+            .collect::<Vec<_>>();
+        results.sort_by_key(|(score, _, _)| std::cmp::Reverse(*score));
+
+        results
+            .into_iter()
+            .map(|(_, tasklist, task)| (tasklist, task))
+            .collect()
+    }
+
+    /// Switches to the tasklist of the selected result and selects the task, expanding its
+    /// parents when they are collapsed.
+    pub fn jump_to_result(&mut self) {
+        let selected = match &self.mode {
+            Mode::Search(search) => search.selected,
+            _ => return,
+        };
+        let (tasklist_id, task) = match self.search_results().get(selected) {
+            Some((tasklist, task)) => (tasklist.id.clone(), (*task).clone()),
+            None => return,
+        };
+        let task_id = task.id.clone();
+        self.mode = Mode::Normal;
+
+        if let Some(tasklist) = self.provider.get_tasklist(&tasklist_id) {
+            let mut parent = tasklist.tasks.iter().find(|t| t.id == task_id);
+            let mut seen = HashSet::new();
+            while let Some(id) = parent
+                .and_then(|t| t.parent.clone())
+                .filter(|id| seen.insert(id.clone()))
+            {
+                parent = tasklist.tasks.iter().find(|t| t.id == id);
+                self.collapsed.remove(&id);
+            }
+        }
+
+        self.tasks_state = TableState::default();
+        self.restore_selection((Some(tasklist_id), Some(task_id)));
+
+        // The view settings are kept, so the task can be hidden by them.
+        if !self
+            .visible_tasks()
+            .iter()
+            .any(|row| row.task.id == task.id)
+        {
+            let cfg = self.tasklist_cfg();
+            let reason = match cfg.filter {
+                _ if !cfg.show_completed && matches!(task.status, Status::Done) => {
+                    "completed tasks are hidden"
+                }
+                Filter::HasDue => "only tasks with a due date are shown",
+                _ => "only overdue tasks are shown",
+            };
+            self.notifications
+                .warning(format!("\"{}\" is not shown, {}", task.title, reason));
+        }
+    }
+
+    pub fn open_move_to_list(&mut self) {
+        if let Some(row) = self.active_row() {
+            self.mode = Mode::MoveToList(TasklistPicker::new(&row.tasklist.id, row.task));
+        }
+    }
+    pub fn move_to_list_next(&mut self) {
+        let len = self.provider.len();
+        if let Mode::MoveToList(picker) = &mut self.mode {
+            picker.next(len);
+        }
+    }
+    pub fn move_to_list_previous(&mut self) {
+        let len = self.provider.len();
+        if let Mode::MoveToList(picker) = &mut self.mode {
+            picker.previous(len);
+        }
+    }
+
+    pub fn move_to_list(&mut self) -> anyhow::Result<()> {
+        let picker = match std::mem::replace(&mut self.mode, Mode::Normal) {
+            Mode::MoveToList(picker) => picker,
+            mode => {
+                self.mode = mode;
+                return Ok(());
+            }
+        };
+        let from = picker.tasklist_id;
+        let to = self
+            .provider
+            .get_nth_tasklist(picker.selected)
+            .ok_or(anyhow::anyhow!("no tasklist selected"))?
+            .id
+            .clone();
+
+        if from == to {
+            return Ok(());
+        }
+
+        self.provider.send(Job::MoveTaskToList {
+            from,
+            task: picker.task,
+            to,
+        })?;
+        self.clamp_task_selection();
+
+        Ok(())
+    }
+
+    pub fn collapse_task(&mut self) {
+        if let Some(row) = self
+            .tasks_state
+            .selected()
+            .and_then(|i| self.visible_tasks().get(i).copied())
+        {
+            if row.has_children {
+                self.collapsed.insert(row.task.id.clone());
+            }
+        }
+    }
+    pub fn expand_task(&mut self) {
+        if let Some(task) = self.active_task() {
+            let id = task.id.clone();
+            self.collapsed.remove(&id);
+        }
+    }
+
+    pub fn toggle_task_state(&mut self) -> anyhow::Result<()> {
+        let TaskRow { task, tasklist, .. } =
+            self.active_row().ok_or(anyhow::anyhow!("no active task"))?;
+
         let status = match task.status {
             Status::Todo => Status::Done,
             Status::Done => Status::Todo,
             Status::Unknown => Status::Unknown,
         };
 
-        self.provider
-            .update_task(
-                &tasklist.id.clone(),
-                &Task {
-                    status,
-                    ..task.clone()
-                },
-            )
-            .await?;
+        let tasklist_id = tasklist.id.clone();
+        let task = Task {
+            status,
+            ..task.clone()
+        };
+
+        self.update_task(tasklist_id, task)?;
+
+        Ok(())
+    }
+
+    /// Updates the task, a conflict with changes made elsewhere switches to the conflict prompt
+    /// once the provider runs into it.
+    fn update_task(&mut self, tasklist_id: String, task: Task) -> anyhow::Result<u64> {
+        let id = self.provider.send(Job::UpdateTask { tasklist_id, task })?;
+        self.clamp_task_selection();
+
+        Ok(id)
+    }
+
+    /// Creates the task with a temporary id, the real one replaces it once the provider is done.
+    fn create_task(&mut self, tasklist_id: String, task: Task) -> anyhow::Result<u64> {
+        let task = Task {
+            id: self.provider.new_id(),
+            ..task
+        };
+
+        self.provider.send(Job::CreateTask { tasklist_id, task })
+    }
+
+    pub fn keep_local(&mut self) -> anyhow::Result<()> {
+        if let Mode::Conflict(conflict) = std::mem::replace(&mut self.mode, Mode::Normal) {
+            let task = Task {
+                etag: conflict.remote.etag.clone(),
+                ..conflict.local
+            };
+
+            self.update_task(conflict.tasklist_id, task)?;
+        }
+
+        Ok(())
+    }
+    pub fn keep_remote(&mut self) -> anyhow::Result<()> {
+        if let Mode::Conflict(_) = std::mem::replace(&mut self.mode, Mode::Normal) {
+            self.sync()?;
+        }
+
+        Ok(())
+    }
+    pub fn merge_conflict(&mut self) {
+        if let Mode::Conflict(conflict) = &self.mode {
+            let task = Task {
+                etag: conflict.remote.etag.clone(),
+                ..conflict.local.clone()
+            };
+
+            self.mode = Mode::TaskForm(TaskForm::edit(&conflict.tasklist_id, &task));
+        }
+    }
+
+    /// Opens the form for a new task, views add it to the first tasklist with their due date.
+    pub fn open_task_form(&mut self) {
+        let form = match self.active_view() {
+            Some(view) => self.view_tasklist().map(|t| TaskForm {
+                due: Input::new(view.default_due()),
+                ..TaskForm::new(&t.id)
+            }),
+            None => self.active_tasklist().map(|t| TaskForm::new(&t.id)),
+        };
+
+        if let Some(form) = form {
+            self.mode = Mode::TaskForm(form);
+        }
+    }
+    /// Tasklist a task added in a view goes to: the configured one, the one of the selected task
+    /// or else the first.
+    fn view_tasklist(&self) -> Option<&Tasklist> {
+        let tasklists = self.provider.get_tasklists();
+        let configured = self
+            .default_tasklist
+            .as_ref()
+            .and_then(|name| tasklists.iter().find(|t| &t.id == name || &t.title == name));
+        let selected = self.active_row().map(|row| row.tasklist);
+
+        configured.or(selected).or(tasklists.first())
+    }
+    pub fn open_edit_form(&mut self) {
+        if let Some(row) = self.active_row() {
+            self.mode = Mode::TaskForm(TaskForm::edit(&row.tasklist.id, row.task));
+        }
+    }
+    pub fn task_form(&mut self) -> Option<&mut TaskForm> {
+        match &mut self.mode {
+            Mode::TaskForm(form) => Some(form),
+            _ => None,
+        }
+    }
+
+    pub fn submit_task_form(&mut self) -> anyhow::Result<()> {
+        let (form, task) = match &self.mode {
+            Mode::TaskForm(form) => (form.clone(), form.to_task()?),
+            _ => return Ok(()),
+        };
+        self.mode = Mode::Normal;
+
+        let id = match form.is_edit() {
+            true => self.update_task(form.tasklist_id.clone(), task)?,
+            false => self.create_task(form.tasklist_id.clone(), task)?,
+        };
+        self.submitted.insert(id, form);
+
+        Ok(())
+    }
+
+    pub fn confirm_delete(&mut self) {
+        if let Some(row) = self.active_row() {
+            self.mode = Mode::ConfirmDelete(row.tasklist.id.clone(), row.task.clone());
+        }
+    }
+
+    pub fn delete_task(&mut self) -> anyhow::Result<()> {
+        let (tasklist_id, task) = match std::mem::replace(&mut self.mode, Mode::Normal) {
+            Mode::ConfirmDelete(tasklist_id, task) => (tasklist_id, task),
+            mode => {
+                self.mode = mode;
+                return Ok(());
+            }
+        };
+
+        let subtree = self
+            .provider
+            .get_tasklists()
+            .iter()
+            .find(|t| t.id == tasklist_id)
+            .map(|t| t.subtree(&task.id))
+            .unwrap_or_default();
+        self.deleted = Some(DeletedTask {
+            tasklist_id: tasklist_id.clone(),
+            task: task.clone(),
+            subtree,
+            deleted_at: Instant::now(),
+        });
+        self.provider.send(Job::DeleteTask { tasklist_id, task })?;
+        self.clamp_task_selection();
+
+        Ok(())
+    }
+
+    pub fn undo_delete(&mut self) -> anyhow::Result<()> {
+        let deleted = match self.deleted.take() {
+            Some(deleted) if deleted.deleted_at.elapsed() < UNDO_TIMEOUT => deleted,
+            _ => return Ok(()),
+        };
+
+        // The copies get new ids, so parents and previous siblings are looked up by the old ones.
+        let mut ids = HashMap::new();
+        for (task, previous) in deleted.subtree {
+            let id = self.provider.new_id();
+            ids.insert(task.id.clone(), id.clone());
+            let new_id = |id: String| ids.get(&id).cloned().unwrap_or(id);
+            let task = Task {
+                id,
+                parent: task.parent.clone().map(new_id),
+                etag: None,
+                ..task
+            };
+            let previous = previous.map(new_id);
+
+            let tasklist_id = deleted.tasklist_id.clone();
+            self.provider.send(Job::CreateTask {
+                tasklist_id: tasklist_id.clone(),
+                task: task.clone(),
+            })?;
+            // New tasks come first, so only the ones that were not first among ordered siblings move.
+            if task.position.is_some() && previous.is_some() {
+                self.provider.send(Job::MoveTask {
+                    tasklist_id,
+                    parent: task.parent.clone(),
+                    task,
+                    previous,
+                })?;
+            }
+        }
 
         Ok(())
     }
@@ -125,14 +974,62 @@ impl fmt::Debug for App {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("App")
             .field("should_quit", &self.should_quit)
-            .field("tasklists", &self.provider)
+            .field("mode", &self.mode)
+            .field("tasklists", self.provider.get_tasklists())
             .field("active_tasklist", &self.active_tasklist)
             .field("active_task", &self.tasks_state)
+            .field("deleted", &self.deleted)
             .finish()
     }
 }
 
-#[derive(Clone, Debug)]
+/// Puts the task under `parent` right after `previous` and renumbers the positions of its new
+/// siblings, the same way the Google Tasks api orders them.
+pub fn place(tasks: &mut [Task], task_id: &str, parent: Option<&str>, previous: Option<&str>) {
+    let mut siblings = tasks
+        .iter()
+        .filter(|t| t.parent.as_deref() == parent && t.id != task_id)
+        .map(|t| (t.position.clone(), t.id.clone()))
+        .collect::<Vec<_>>();
+    siblings.sort();
+
+    let mut order = siblings.into_iter().map(|(_, id)| id).collect::<Vec<_>>();
+    let i = previous
+        .and_then(|previous| order.iter().position(|id| id == previous))
+        .map_or(0, |i| i + 1);
+    order.insert(i, task_id.to_string());
+
+    for task in tasks.iter_mut() {
+        if task.id == task_id {
+            task.parent = parent.map(|p| p.to_string());
+        }
+        if let Some(i) = order.iter().position(|id| id == &task.id) {
+            task.position = Some(format!("{:020}", i));
+        }
+    }
+}
+
+/// Removes the task with all its subtasks, returning them parents first.
+pub fn remove_subtree(tasks: &mut Vec<Task>, task_id: &str) -> Vec<Task> {
+    let mut removed = Vec::new();
+    let mut ids = vec![task_id.to_string()];
+    while let Some(id) = ids.pop() {
+        if let Some(i) = tasks.iter().position(|t| t.id == id) {
+            let task = tasks.remove(i);
+            ids.extend(
+                tasks
+                    .iter()
+                    .filter(|t| t.parent.as_ref() == Some(&task.id))
+                    .map(|t| t.id.clone()),
+            );
+            removed.push(task);
+        }
+    }
+
+    removed
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Tasklist {
     pub id: String,
     pub title: String,
@@ -147,31 +1044,222 @@ impl Tasklist {
         }
     }
 
-    pub fn len(&self) -> usize {
-        self.tasks.len()
+    /// Direct children of `parent` (or root tasks for `None`) ordered by position.
+    ///
+    /// Tasks whose parent is not in this list are treated as root tasks.
+    pub fn children(&self, parent: Option<&str>) -> Vec<&Task> {
+        self.children_by_parent()
+            .remove(&parent)
+            .unwrap_or_default()
     }
-    pub fn is_empty(&self) -> bool {
-        self.tasks.is_empty()
+
+    /// The task and its subtasks, parents first, each with the id of the sibling before it.
+    pub fn subtree(&self, task_id: &str) -> Vec<(Task, Option<String>)> {
+        let children = self.children_by_parent();
+        let previous =
+            |siblings: &[&Task], i: usize| i.checked_sub(1).map(|i| siblings[i].id.clone());
+
+        let mut subtree = Vec::new();
+        let mut visited = HashSet::new();
+        let Some(task) = self.tasks.iter().find(|t| t.id == task_id) else {
+            return subtree;
+        };
+        let siblings = children
+            .get(&task.parent.as_deref())
+            .cloned()
+            .unwrap_or_default();
+        let i = siblings.iter().position(|t| t.id == task_id).unwrap_or(0);
+        subtree.push(((*task).clone(), previous(&siblings, i)));
+        visited.insert(task_id);
+
+        let mut next = 0;
+        while let Some((task, _)) = subtree.get(next) {
+            next += 1;
+            let siblings = children
+                .get(&Some(task.id.as_str()))
+                .cloned()
+                .unwrap_or_default();
+            let found = siblings
+                .iter()
+                .enumerate()
+                .filter(|(_, t)| visited.insert(t.id.as_str()))
+                .map(|(i, t)| ((*t).clone(), previous(&siblings, i)))
+                .collect::<Vec<_>>();
+            subtree.extend(found);
+        }
+
+        subtree
     }
-    pub fn get(&self, index: usize) -> Option<&Task> {
-        self.tasks.get(index)
+
+    /// Tasks by the id of their parent, as returned by `children`.
+    pub fn children_by_parent(&self) -> HashMap<Option<&str>, Vec<&Task>> {
+        let ids = self
+            .tasks
+            .iter()
+            .map(|t| t.id.as_str())
+            .collect::<HashSet<_>>();
+
+        let mut children = HashMap::<_, Vec<_>>::new();
+        for task in self.tasks.iter() {
+            let parent = task.parent.as_deref().filter(|p| ids.contains(p));
+            children.entry(parent).or_default().push(task);
+        }
+        for tasks in children.values_mut() {
+            tasks.sort_by(|a, b| match (&a.position, &b.position) {
+                (Some(a), Some(b)) => a.cmp(b),
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (None, None) => std::cmp::Ordering::Equal,
+            });
+        }
+
+        children
+    }
+
+    /// Tasks in display order, skipping subtasks of collapsed tasks.
+    ///
+    /// Siblings are sorted as set in `cfg`, subtasks of tasks hidden by its filters take their
+    /// place. Tasks whose parents form a cycle can not be reached from the root, they come last.
+    pub fn tree(&self, collapsed: &HashSet<String>, cfg: &TasklistCfg) -> Vec<TaskRow<'_>> {
+        let mut walk = Walk {
+            tasklist: self,
+            children: self.children_by_parent(),
+            visited: HashSet::new(),
+            collapsed,
+            cfg,
+            rows: Vec::new(),
+        };
+        walk.push_rows(None, 0);
+
+        let mut reached = HashSet::new();
+        reach(&walk.children, None, &mut reached);
+        for task in self.tasks.iter() {
+            if reached.insert(task.id.as_str()) {
+                reach(&walk.children, Some(&task.id), &mut reached);
+                walk.push_tasks(vec![task], 0);
+            }
+        }
+
+        walk.rows
+    }
+}
+
+/// Adds the ids of all tasks below `parent` to `reached`.
+fn reach<'a>(
+    children: &HashMap<Option<&'a str>, Vec<&'a Task>>,
+    parent: Option<&'a str>,
+    reached: &mut HashSet<&'a str>,
+) {
+    let mut stack = vec![parent];
+    while let Some(parent) = stack.pop() {
+        for task in children.get(&parent).into_iter().flatten() {
+            if reached.insert(task.id.as_str()) {
+                stack.push(Some(task.id.as_str()));
+            }
+        }
     }
 }
 
-#[derive(Clone, Debug)]
+/// State of `Tasklist::tree` while it goes through the tasks.
+struct Walk<'a, 'c> {
+    tasklist: &'a Tasklist,
+    children: HashMap<Option<&'a str>, Vec<&'a Task>>,
+    visited: HashSet<&'a str>,
+    collapsed: &'c HashSet<String>,
+    cfg: &'c TasklistCfg,
+    rows: Vec<TaskRow<'a>>,
+}
+impl<'a> Walk<'a, '_> {
+    fn push_rows(&mut self, parent: Option<&'a str>, depth: usize) {
+        let children = self.children.get(&parent).cloned().unwrap_or_default();
+        self.push_tasks(children, depth);
+    }
+
+    fn push_tasks(&mut self, mut tasks: Vec<&'a Task>, depth: usize) {
+        match self.cfg.sort {
+            Sort::Position => {}
+            // Tasks without a due date go last.
+            Sort::Due => tasks.sort_by_key(|t| (t.due.is_none(), t.due.clone())),
+            Sort::Title => tasks.sort_by_key(|t| t.title.to_lowercase()),
+            Sort::Status => tasks.sort_by_key(|t| match t.status {
+                Status::Todo => 0,
+                Status::Done => 1,
+                Status::Unknown => 2,
+            }),
+        }
+
+        for task in tasks {
+            if !self.visited.insert(&task.id) {
+                continue;
+            }
+            let has_children = self
+                .children
+                .get(&Some(task.id.as_str()))
+                .is_some_and(|c| !c.is_empty());
+            let is_collapsed = self.collapsed.contains(&task.id);
+
+            if !is_shown(task, self.cfg) {
+                if has_children {
+                    self.push_rows(Some(&task.id), depth);
+                }
+                continue;
+            }
+
+            self.rows.push(TaskRow {
+                task,
+                tasklist: self.tasklist,
+                depth,
+                has_children,
+                collapsed: is_collapsed,
+            });
+
+            if has_children && !is_collapsed {
+                self.push_rows(Some(&task.id), depth + 1);
+            }
+        }
+    }
+}
+
+fn is_shown(task: &Task, cfg: &TasklistCfg) -> bool {
+    let completed = matches!(task.status, Status::Done);
+
+    (cfg.show_completed || !completed)
+        && match cfg.filter {
+            Filter::All => true,
+            Filter::HasDue => task.due.is_some(),
+            Filter::Overdue => !completed && task.due.as_ref().is_some_and(|d| d.is_past()),
+        }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TaskRow<'a> {
+    pub task: &'a Task,
+    pub tasklist: &'a Tasklist,
+    pub depth: usize,
+    pub has_children: bool,
+    pub collapsed: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Status {
     Todo,
     Done,
     Unknown,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Task {
     pub id: String,
     pub status: Status,
     pub title: String,
     pub due: Option<TimestampType>,
     pub notes: Option<String>,
+    pub parent: Option<String>,
+    pub position: Option<String>,
+    /// Version of the task on the provider, changes are rejected when it moved on.
+    pub etag: Option<String>,
+    pub updated: Option<String>,
 }
 impl Task {
     pub fn new(
@@ -187,6 +1275,136 @@ impl Task {
             title: title.to_string(),
             due,
             notes: notes.map(|s| s.to_string()),
+            parent: None,
+            position: None,
+            etag: None,
+            updated: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(id: &str, parent: Option<&str>, position: &str) -> Task {
+        Task {
+            parent: parent.map(|p| p.to_string()),
+            position: Some(position.to_string()),
+            ..Task::new(id, Status::Todo, id, None, None)
         }
     }
+
+    fn ids(tasklist: &Tasklist, collapsed: &[&str], cfg: &TasklistCfg) -> Vec<(String, usize)> {
+        let collapsed = collapsed.iter().map(|c| c.to_string()).collect();
+        tasklist
+            .tree(&collapsed, cfg)
+            .iter()
+            .map(|r| (r.task.id.clone(), r.depth))
+            .collect()
+    }
+
+    fn rows(rows: &[(&str, usize)]) -> Vec<(String, usize)> {
+        rows.iter().map(|(id, d)| (id.to_string(), *d)).collect()
+    }
+
+    #[test]
+    fn subtree_lists_parents_first_with_previous_siblings() {
+        let tasklist = Tasklist::new(
+            "l".to_string(),
+            "List".to_string(),
+            &[
+                task("a", None, "1"),
+                task("b", None, "2"),
+                task("b2", Some("b"), "2"),
+                task("b1", Some("b"), "1"),
+                task("b11", Some("b1"), "1"),
+                task("c", None, "3"),
+            ],
+        );
+
+        let subtree = tasklist
+            .subtree("b")
+            .into_iter()
+            .map(|(t, previous)| (t.id, previous))
+            .collect::<Vec<_>>();
+        let some = |id: &str| Some(id.to_string());
+        assert_eq!(
+            subtree,
+            vec![
+                ("b".to_string(), some("a")),
+                ("b1".to_string(), None),
+                ("b2".to_string(), some("b1")),
+                ("b11".to_string(), None),
+            ]
+        );
+        assert!(tasklist.subtree("missing").is_empty());
+    }
+
+    #[test]
+    fn tree_nests_subtasks_by_position() {
+        let tasklist = Tasklist::new(
+            "l".to_string(),
+            "List".to_string(),
+            &[
+                task("b", None, "2"),
+                task("b2", Some("b"), "2"),
+                task("a", None, "1"),
+                task("b1", Some("b"), "1"),
+                task("orphan", Some("missing"), "3"),
+            ],
+        );
+        let cfg = TasklistCfg::default();
+
+        assert_eq!(
+            ids(&tasklist, &[], &cfg),
+            rows(&[("a", 0), ("b", 0), ("b1", 1), ("b2", 1), ("orphan", 0)])
+        );
+        assert_eq!(
+            ids(&tasklist, &["b"], &cfg),
+            rows(&[("a", 0), ("b", 0), ("orphan", 0)])
+        );
+    }
+
+    #[test]
+    fn tree_shows_subtasks_of_hidden_tasks_in_their_place() {
+        let tasklist = Tasklist::new(
+            "l".to_string(),
+            "List".to_string(),
+            &[
+                Task {
+                    status: Status::Done,
+                    ..task("a", None, "1")
+                },
+                task("a1", Some("a"), "1"),
+                task("b", None, "2"),
+            ],
+        );
+        let cfg = TasklistCfg {
+            show_completed: false,
+            ..Default::default()
+        };
+
+        assert_eq!(ids(&tasklist, &[], &cfg), rows(&[("a1", 0), ("b", 0)]));
+    }
+
+    #[test]
+    fn tree_shows_tasks_with_a_parent_cycle_once() {
+        let tasklist = Tasklist::new(
+            "l".to_string(),
+            "List".to_string(),
+            &[
+                task("root", None, "1"),
+                task("a", Some("b"), "1"),
+                task("b", Some("a"), "1"),
+            ],
+        );
+        let cfg = TasklistCfg::default();
+
+        assert_eq!(
+            ids(&tasklist, &[], &cfg),
+            rows(&[("root", 0), ("a", 0), ("b", 1)])
+        );
+        assert_eq!(ids(&tasklist, &["a"], &cfg), rows(&[("root", 0), ("a", 0)]));
+    }
 }
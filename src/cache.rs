@@ -0,0 +1,937 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::{Task, Tasklist},
+    provider::{Conflict, Conflicts, Loader, Provider},
+};
+
+/// Change made while offline, replayed against the inner provider on the next sync.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+enum Operation {
+    CreateTasklist {
+        id: String,
+        title: String,
+    },
+    RenameTasklist {
+        tasklist_id: String,
+        title: String,
+    },
+    DeleteTasklist {
+        tasklist_id: String,
+    },
+    CreateTask {
+        tasklist_id: String,
+        task: Task,
+    },
+    UpdateTask {
+        tasklist_id: String,
+        task: Task,
+    },
+    DeleteTask {
+        tasklist_id: String,
+        task_id: String,
+    },
+    MoveTask {
+        tasklist_id: String,
+        task_id: String,
+        parent: Option<String>,
+        previous: Option<String>,
+    },
+}
+impl Operation {
+    /// Replaces ids of items created offline with the ids assigned by the provider.
+    fn resolve(self, ids: &HashMap<String, String>) -> Self {
+        let id = |id: String| ids.get(&id).cloned().unwrap_or(id);
+        let task = |task: Task| Task {
+            id: id(task.id),
+            parent: task.parent.map(id),
+            ..task
+        };
+
+        match self {
+            Operation::CreateTasklist { id, title } => Operation::CreateTasklist { id, title },
+            Operation::RenameTasklist { tasklist_id, title } => Operation::RenameTasklist {
+                tasklist_id: id(tasklist_id),
+                title,
+            },
+            Operation::DeleteTasklist { tasklist_id } => Operation::DeleteTasklist {
+                tasklist_id: id(tasklist_id),
+            },
+            Operation::CreateTask {
+                tasklist_id,
+                task: t,
+            } => Operation::CreateTask {
+                tasklist_id: id(tasklist_id),
+                task: Task {
+                    parent: t.parent.clone().map(id),
+                    ..t
+                },
+            },
+            Operation::UpdateTask {
+                tasklist_id,
+                task: t,
+            } => Operation::UpdateTask {
+                tasklist_id: id(tasklist_id),
+                task: task(t),
+            },
+            Operation::DeleteTask {
+                tasklist_id,
+                task_id,
+            } => Operation::DeleteTask {
+                tasklist_id: id(tasklist_id),
+                task_id: id(task_id),
+            },
+            Operation::MoveTask {
+                tasklist_id,
+                task_id,
+                parent,
+                previous,
+            } => Operation::MoveTask {
+                tasklist_id: id(tasklist_id),
+                task_id: id(task_id),
+                parent: parent.map(id),
+                previous: previous.map(id),
+            },
+        }
+    }
+
+    /// Runs the operation against the provider, returns the id of the created item.
+    async fn execute(&self, provider: &mut dyn Provider) -> anyhow::Result<Option<String>> {
+        Ok(match self {
+            Operation::CreateTasklist { title, .. } => Some(provider.create_tasklist(title).await?),
+            Operation::RenameTasklist { tasklist_id, title } => {
+                provider.rename_tasklist(tasklist_id, title).await?;
+                None
+            }
+            Operation::DeleteTasklist { tasklist_id } => {
+                provider.delete_tasklist(tasklist_id).await?;
+                None
+            }
+            Operation::CreateTask { tasklist_id, task } => {
+                Some(provider.create_task(tasklist_id, task).await?)
+            }
+            Operation::UpdateTask { tasklist_id, task } => {
+                provider.update_task(tasklist_id, task).await?;
+                None
+            }
+            Operation::DeleteTask {
+                tasklist_id,
+                task_id,
+            } => {
+                provider.delete_task(tasklist_id, task_id).await?;
+                None
+            }
+            Operation::MoveTask {
+                tasklist_id,
+                task_id,
+                parent,
+                previous,
+            } => {
+                provider
+                    .move_task(tasklist_id, task_id, parent.as_deref(), previous.as_deref())
+                    .await?;
+                None
+            }
+        })
+    }
+
+    /// Id of the item this operation creates.
+    fn created_id(&self) -> Option<&str> {
+        match self {
+            Operation::CreateTasklist { id, .. } => Some(id),
+            Operation::CreateTask { task, .. } => Some(&task.id),
+            _ => None,
+        }
+    }
+
+    /// Applies the operation to the cached tasklists.
+    fn apply(&self, tasklists: &mut Vec<Tasklist>) {
+        match self {
+            Operation::CreateTasklist { id, title } => {
+                tasklists.push(Tasklist::new(id.clone(), title.clone(), &[]))
+            }
+            Operation::RenameTasklist { tasklist_id, title } => {
+                if let Some(t) = tasklists.iter_mut().find(|t| &t.id == tasklist_id) {
+                    t.title = title.clone();
+                }
+            }
+            Operation::DeleteTasklist { tasklist_id } => tasklists.retain(|t| &t.id != tasklist_id),
+            Operation::CreateTask { tasklist_id, task } => {
+                if let Some(tasks) = tasks_mut(tasklists, tasklist_id) {
+                    // Inserted tasks go first among their siblings.
+                    tasks.push(Task {
+                        position: Some(String::new()),
+                        ..task.clone()
+                    });
+                }
+            }
+            Operation::UpdateTask { tasklist_id, task } => {
+                if let Some(t) = tasks_mut(tasklists, tasklist_id)
+                    .and_then(|tasks| tasks.iter_mut().find(|t| t.id == task.id))
+                {
+                    *t = Task {
+                        parent: t.parent.clone(),
+                        position: t.position.clone(),
+                        ..task.clone()
+                    };
+                }
+            }
+            Operation::DeleteTask {
+                tasklist_id,
+                task_id,
+            } => {
+                if let Some(tasks) = tasks_mut(tasklists, tasklist_id) {
+                    let mut removed = vec![task_id.clone()];
+                    while let Some(id) = removed.pop() {
+                        removed.extend(
+                            tasks
+                                .iter()
+                                .filter(|t| t.parent.as_ref() == Some(&id))
+                                .map(|t| t.id.clone()),
+                        );
+                        tasks.retain(|t| t.id != id);
+                    }
+                }
+            }
+            Operation::MoveTask {
+                tasklist_id,
+                task_id,
+                parent,
+                previous,
+            } => {
+                if let Some(tasks) = tasks_mut(tasklists, tasklist_id) {
+                    let position = match previous {
+                        Some(previous) => tasks
+                            .iter()
+                            .find(|t| &t.id == previous)
+                            .and_then(|t| t.position.clone())
+                            .map(|p| next_position(&p)),
+                        None => Some(String::new()),
+                    };
+
+                    if let Some(t) = tasks.iter_mut().find(|t| &t.id == task_id) {
+                        t.parent = parent.clone();
+                        t.position = position;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn tasks_mut<'a>(tasklists: &'a mut [Tasklist], tasklist_id: &str) -> Option<&'a mut Vec<Task>> {
+    tasklists
+        .iter_mut()
+        .find(|t| t.id == tasklist_id)
+        .map(|t| &mut t.tasks)
+}
+
+/// Positions are compared as strings, so appending to one sorts right after it.
+fn next_position(position: &str) -> String {
+    format!("{}5", position)
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Cache {
+    tasklists: Vec<Tasklist>,
+    queue: Vec<Operation>,
+}
+
+/// Keeps a copy of the inner provider's tasklists on disk and queues changes made while it is
+/// unreachable.
+pub struct CachedProvider<P: Provider> {
+    inner: P,
+    path: PathBuf,
+
+    cache: Cache,
+    /// Changes are only sent straight to the inner provider after it synced successfully.
+    online: bool,
+}
+
+impl<P: Provider> CachedProvider<P> {
+    pub fn new(inner: P, path: PathBuf) -> anyhow::Result<Self> {
+        let cache = match std::fs::read_to_string(&path) {
+            Ok(content) => match serde_json::from_str(&content) {
+                Ok(cache) => cache,
+                // A broken or outdated cache is kept for inspection, the inner provider refills it.
+                Err(err) => {
+                    let aside = path.with_extension("json.corrupt");
+                    eprintln!(
+                        "could not read the cache {}, moving it to {}: {}",
+                        path.display(),
+                        aside.display(),
+                        err
+                    );
+                    std::fs::rename(&path, &aside)?;
+                    Cache::default()
+                }
+            },
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Cache::default(),
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(Self {
+            inner,
+            path,
+            cache,
+            online: false,
+        })
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string(&self.cache)?)?;
+
+        Ok(())
+    }
+
+    /// Sends the change to the inner provider, or queues it when offline.
+    async fn run(&mut self, operation: Operation) -> anyhow::Result<Option<String>> {
+        if self.online && self.cache.queue.is_empty() {
+            match operation.execute(&mut self.inner).await {
+                Ok(id) => {
+                    self.cache.tasklists = self.inner.get_tasklists().clone();
+                    self.save()?;
+
+                    return Ok(id);
+                }
+                Err(err) if self.inner.is_offline_error(&err) => self.online = false,
+                Err(err) => return Err(err),
+            }
+        }
+
+        let id = operation.created_id().map(|id| id.to_string());
+        operation.apply(&mut self.cache.tasklists);
+        self.cache.queue.push(operation);
+        self.save()?;
+
+        Ok(id)
+    }
+
+    /// Gives the queued updates of `task` from `from` on the etag its replayed update got back,
+    /// they were all made on top of the version it replaced.
+    fn pass_etag(
+        &mut self,
+        from: usize,
+        ids: &HashMap<String, String>,
+        task: &Task,
+        etag: Option<String>,
+    ) {
+        for operation in self.cache.queue[from..].iter_mut() {
+            if let Operation::UpdateTask { task: later, .. } = operation {
+                let id = ids.get(&later.id).unwrap_or(&later.id);
+                if id == &task.id && later.etag == task.etag {
+                    later.etag = etag.clone();
+                }
+            }
+        }
+    }
+
+    /// Replays queued changes, stops at the first one that can not reach the provider.
+    ///
+    /// Changes the provider rejects stay queued and the first such error is returned. Conflicts
+    /// are taken out of the queue and returned together, the prompt decides which side to keep.
+    async fn replay(&mut self) -> anyhow::Result<()> {
+        let mut ids = HashMap::new();
+        let mut conflicts = Vec::new();
+        let mut rejected = Vec::new();
+
+        let mut i = 0;
+        while i < self.cache.queue.len() {
+            let operation = self.cache.queue[i].clone().resolve(&ids);
+            let local_id = operation.created_id().map(|id| id.to_string());
+
+            match operation.execute(&mut self.inner).await {
+                Ok(id) => {
+                    if let (Some(local_id), Some(id)) = (local_id, id) {
+                        ids.insert(local_id, id);
+                    }
+                    if let Operation::UpdateTask { tasklist_id, task } = &operation {
+                        let etag = self
+                            .inner
+                            .get_task(tasklist_id, &task.id)
+                            .and_then(|t| t.etag.clone());
+                        self.pass_etag(i + 1, &ids, task, etag);
+                    }
+                    self.cache.queue.remove(i);
+                }
+                Err(err) if self.inner.is_offline_error(&err) => {
+                    // Keep the remaining changes pointing at the ids the provider assigned.
+                    self.cache.queue = self
+                        .cache
+                        .queue
+                        .drain(..)
+                        .map(|o| o.resolve(&ids))
+                        .collect();
+                    self.save()?;
+
+                    return match conflicts.is_empty() {
+                        true => Err(err),
+                        false => Err(Conflicts(conflicts).into()),
+                    };
+                }
+                Err(err) => match err.downcast::<Conflict>() {
+                    Ok(conflict) => {
+                        conflicts.push(conflict);
+                        self.cache.queue.remove(i);
+                    }
+                    Err(err) => {
+                        rejected.push(err);
+                        self.cache.queue[i] = operation;
+                        i += 1;
+                    }
+                },
+            }
+
+            self.save()?;
+        }
+
+        if !conflicts.is_empty() {
+            return Err(Conflicts(conflicts).into());
+        }
+        match rejected.len() {
+            0 => Ok(()),
+            n => Err(rejected.remove(0).context(format!(
+                "{} queued changes were rejected, they are kept for the next sync",
+                n
+            ))),
+        }
+    }
+}
+
+fn local_id() -> String {
+    format!("local-{}", chrono::Utc::now().timestamp_nanos())
+}
+
+#[async_trait::async_trait]
+impl<P: Provider> Provider for CachedProvider<P> {
+    fn get_tasklists(&self) -> &Vec<Tasklist> {
+        &self.cache.tasklists
+    }
+
+    async fn sync(&mut self) -> anyhow::Result<()> {
+        // Loading first makes sure the inner provider knows every tasklist the queue touches,
+        // replayed changes then update the inner tasklists as they go.
+        if let Err(err) = self.inner.sync().await {
+            self.online = false;
+            return Err(err);
+        }
+
+        let replayed = self.replay().await;
+        if let Err(err) = &replayed {
+            if self.inner.is_offline_error(err) {
+                self.online = false;
+                return replayed;
+            }
+        }
+
+        self.online = true;
+        self.cache.tasklists = self.inner.get_tasklists().clone();
+        for operation in self.cache.queue.iter() {
+            operation.apply(&mut self.cache.tasklists);
+        }
+        self.save()?;
+
+        replayed
+    }
+
+    fn loader(&self) -> Option<Loader> {
+        self.inner.loader()
+    }
+
+    fn set_tasklists(&mut self, tasklists: Vec<Tasklist>) {
+        // Queued changes would disappear from the view until the next sync replays them.
+        if !self.cache.queue.is_empty() {
+            return;
+        }
+
+        self.inner.set_tasklists(tasklists.clone());
+        self.cache.tasklists = tasklists;
+        self.online = true;
+        // A failed write only loses the cache, the next change saves it again.
+        let _ = self.save();
+    }
+
+    fn is_offline_error(&self, err: &anyhow::Error) -> bool {
+        self.inner.is_offline_error(err)
+    }
+
+    fn pending_changes(&self) -> usize {
+        self.cache.queue.len()
+    }
+
+    async fn create_tasklist(&mut self, title: &str) -> anyhow::Result<String> {
+        let id = local_id();
+        let id = self
+            .run(Operation::CreateTasklist {
+                id: id.clone(),
+                title: title.to_string(),
+            })
+            .await?
+            .unwrap_or(id);
+
+        Ok(id)
+    }
+
+    async fn rename_tasklist(&mut self, tasklist_id: &str, title: &str) -> anyhow::Result<()> {
+        self.run(Operation::RenameTasklist {
+            tasklist_id: tasklist_id.to_string(),
+            title: title.to_string(),
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_tasklist(&mut self, tasklist_id: &str) -> anyhow::Result<()> {
+        self.run(Operation::DeleteTasklist {
+            tasklist_id: tasklist_id.to_string(),
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn update_task(&mut self, tasklist_id: &str, task: &Task) -> anyhow::Result<()> {
+        self.run(Operation::UpdateTask {
+            tasklist_id: tasklist_id.to_string(),
+            task: task.clone(),
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn create_task(&mut self, tasklist_id: &str, task: &Task) -> anyhow::Result<String> {
+        let id = local_id();
+        let id = self
+            .run(Operation::CreateTask {
+                tasklist_id: tasklist_id.to_string(),
+                task: Task {
+                    id: id.clone(),
+                    ..task.clone()
+                },
+            })
+            .await?
+            .unwrap_or(id);
+
+        Ok(id)
+    }
+
+    async fn delete_task(&mut self, tasklist_id: &str, task_id: &str) -> anyhow::Result<()> {
+        self.run(Operation::DeleteTask {
+            tasklist_id: tasklist_id.to_string(),
+            task_id: task_id.to_string(),
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn move_task(
+        &mut self,
+        tasklist_id: &str,
+        task_id: &str,
+        parent: Option<&str>,
+        previous: Option<&str>,
+    ) -> anyhow::Result<()> {
+        self.run(Operation::MoveTask {
+            tasklist_id: tasklist_id.to_string(),
+            task_id: task_id.to_string(),
+            parent: parent.map(|p| p.to_string()),
+            previous: previous.map(|p| p.to_string()),
+        })
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::Status;
+
+    /// Provider keeping its tasklists in memory, updates titled "rejected" fail and ones with
+    /// an etag other than the task's conflict. Every update gives the task a new etag.
+    #[derive(Default)]
+    struct Remote {
+        tasklists: Vec<Tasklist>,
+        offline: bool,
+        created: usize,
+        updated: usize,
+    }
+    impl Remote {
+        fn check(&self) -> anyhow::Result<()> {
+            match self.offline {
+                true => anyhow::bail!("offline"),
+                false => Ok(()),
+            }
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Provider for Remote {
+        fn get_tasklists(&self) -> &Vec<Tasklist> {
+            &self.tasklists
+        }
+        async fn sync(&mut self) -> anyhow::Result<()> {
+            self.check()
+        }
+        fn is_offline_error(&self, err: &anyhow::Error) -> bool {
+            err.to_string() == "offline"
+        }
+
+        async fn create_tasklist(&mut self, title: &str) -> anyhow::Result<String> {
+            self.check()?;
+            self.created += 1;
+            let id = format!("remote-{}", self.created);
+            self.tasklists
+                .push(Tasklist::new(id.clone(), title.to_string(), &[]));
+            Ok(id)
+        }
+        async fn rename_tasklist(&mut self, _: &str, _: &str) -> anyhow::Result<()> {
+            self.check()
+        }
+        async fn delete_tasklist(&mut self, tasklist_id: &str) -> anyhow::Result<()> {
+            self.check()?;
+            self.tasklists.retain(|t| t.id != tasklist_id);
+            Ok(())
+        }
+        async fn update_task(&mut self, tasklist_id: &str, task: &Task) -> anyhow::Result<()> {
+            self.check()?;
+            let remote = tasks_mut(&mut self.tasklists, tasklist_id)
+                .and_then(|tasks| tasks.iter_mut().find(|t| t.id == task.id))
+                .ok_or(anyhow::anyhow!("task {} not found", task.id))?;
+            if task.title == "rejected" {
+                anyhow::bail!("rejected");
+            }
+            if task.etag.is_some() && task.etag != remote.etag {
+                return Err(Conflict {
+                    tasklist_id: tasklist_id.to_string(),
+                    local: task.clone(),
+                    remote: remote.clone(),
+                }
+                .into());
+            }
+            self.updated += 1;
+            *remote = Task {
+                etag: Some(format!("v{}", self.updated)),
+                ..task.clone()
+            };
+            Ok(())
+        }
+        async fn create_task(&mut self, tasklist_id: &str, task: &Task) -> anyhow::Result<String> {
+            self.check()?;
+            if task.title == "rejected" {
+                anyhow::bail!("rejected");
+            }
+            self.created += 1;
+            let id = format!("remote-{}", self.created);
+            tasks_mut(&mut self.tasklists, tasklist_id)
+                .ok_or(anyhow::anyhow!("tasklist {} not found", tasklist_id))?
+                .push(Task {
+                    id: id.clone(),
+                    ..task.clone()
+                });
+            Ok(id)
+        }
+        async fn delete_task(&mut self, tasklist_id: &str, task_id: &str) -> anyhow::Result<()> {
+            self.check()?;
+            if let Some(tasks) = tasks_mut(&mut self.tasklists, tasklist_id) {
+                tasks.retain(|t| t.id != task_id);
+            }
+            Ok(())
+        }
+        async fn move_task(
+            &mut self,
+            _: &str,
+            _: &str,
+            _: Option<&str>,
+            _: Option<&str>,
+        ) -> anyhow::Result<()> {
+            self.check()
+        }
+    }
+
+    fn block_on<F: std::future::Future>(f: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(f)
+    }
+
+    fn task(id: &str, title: &str) -> Task {
+        Task::new(id, Status::Todo, title, None, None)
+    }
+
+    /// Cached provider that starts offline, so every change is queued.
+    fn offline(name: &str, tasks: &[Task]) -> CachedProvider<Remote> {
+        let path = std::env::temp_dir().join(format!("tudo-cache-{}.json", name));
+        let _ = std::fs::remove_file(&path);
+
+        let remote = Remote {
+            tasklists: vec![Tasklist::new("l".to_string(), "List".to_string(), tasks)],
+            offline: true,
+            ..Default::default()
+        };
+        let mut cached = CachedProvider::new(remote, path).unwrap();
+        cached.cache.tasklists = cached.inner.tasklists.clone();
+
+        cached
+    }
+
+    fn titles(tasklists: &[Tasklist]) -> Vec<&str> {
+        let mut tasks = tasklists[0].tasks.iter().collect::<Vec<_>>();
+        tasks.sort_by(|a, b| a.position.cmp(&b.position));
+        tasks.iter().map(|t| t.title.as_str()).collect()
+    }
+
+    #[test]
+    fn apply_updates_and_deletes_tasks() {
+        let mut tasklists = vec![Tasklist::new(
+            "l".to_string(),
+            "List".to_string(),
+            &[
+                Task {
+                    position: Some("1".to_string()),
+                    ..task("a", "A")
+                },
+                Task {
+                    parent: Some("a".to_string()),
+                    ..task("b", "B")
+                },
+                Task {
+                    position: Some("2".to_string()),
+                    ..task("c", "C")
+                },
+            ],
+        )];
+
+        Operation::UpdateTask {
+            tasklist_id: "l".to_string(),
+            task: task("c", "Renamed"),
+        }
+        .apply(&mut tasklists);
+        let c = &tasklists[0].tasks[2];
+        assert_eq!(c.title, "Renamed");
+        assert_eq!(c.position.as_deref(), Some("2"));
+
+        Operation::DeleteTask {
+            tasklist_id: "l".to_string(),
+            task_id: "a".to_string(),
+        }
+        .apply(&mut tasklists);
+        assert_eq!(titles(&tasklists), ["Renamed"]);
+    }
+
+    #[test]
+    fn apply_places_created_and_moved_tasks() {
+        let mut tasklists = vec![Tasklist::new(
+            "l".to_string(),
+            "List".to_string(),
+            &[
+                Task {
+                    position: Some("1".to_string()),
+                    ..task("a", "A")
+                },
+                Task {
+                    position: Some("2".to_string()),
+                    ..task("b", "B")
+                },
+            ],
+        )];
+
+        Operation::CreateTask {
+            tasklist_id: "l".to_string(),
+            task: task("n", "New"),
+        }
+        .apply(&mut tasklists);
+        assert_eq!(titles(&tasklists), ["New", "A", "B"]);
+
+        Operation::MoveTask {
+            tasklist_id: "l".to_string(),
+            task_id: "n".to_string(),
+            parent: None,
+            previous: Some("a".to_string()),
+        }
+        .apply(&mut tasklists);
+        assert_eq!(titles(&tasklists), ["A", "New", "B"]);
+    }
+
+    #[test]
+    fn replay_uses_the_ids_of_created_tasks() {
+        let mut cached = offline("ids", &[]);
+        block_on(async {
+            let id = cached.create_task("l", &task("", "New")).await.unwrap();
+            cached
+                .update_task("l", &task(&id, "Renamed"))
+                .await
+                .unwrap();
+            assert_eq!(cached.pending_changes(), 2);
+
+            cached.inner.offline = false;
+            cached.sync().await.unwrap();
+        });
+
+        assert_eq!(cached.pending_changes(), 0);
+        let tasks = &cached.get_tasklists()[0].tasks;
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].id, "remote-1");
+        assert_eq!(tasks[0].title, "Renamed");
+    }
+
+    #[test]
+    fn replay_keeps_rejected_changes() {
+        let mut cached = offline("rejected", &[task("a", "A"), task("b", "B")]);
+        let result = block_on(async {
+            cached
+                .update_task("l", &task("a", "rejected"))
+                .await
+                .unwrap();
+            cached
+                .update_task("l", &task("b", "Renamed"))
+                .await
+                .unwrap();
+
+            cached.inner.offline = false;
+            cached.sync().await
+        });
+
+        assert!(result.is_err());
+        assert_eq!(cached.pending_changes(), 1);
+        let tasks = &cached.get_tasklists()[0].tasks;
+        assert_eq!(tasks[0].title, "rejected");
+        assert_eq!(tasks[1].title, "Renamed");
+    }
+
+    #[test]
+    fn replay_returns_every_conflict() {
+        let stale = |id: &str| Task {
+            etag: Some("stale".to_string()),
+            ..task(id, "Local")
+        };
+        let mut cached = offline("conflicts", &[task("a", "A"), task("b", "B")]);
+        let result = block_on(async {
+            cached.update_task("l", &stale("a")).await.unwrap();
+            cached.update_task("l", &stale("b")).await.unwrap();
+
+            cached.inner.offline = false;
+            cached.sync().await
+        });
+
+        let Conflicts(conflicts) = result.unwrap_err().downcast::<Conflicts>().unwrap();
+        let remote = conflicts
+            .iter()
+            .map(|c| c.remote.title.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(remote, ["A", "B"]);
+        assert_eq!(cached.pending_changes(), 0);
+    }
+
+    #[test]
+    fn replay_passes_etags_to_later_edits_of_a_task() {
+        let a = Task {
+            etag: Some("v0".to_string()),
+            ..task("a", "A")
+        };
+        let mut cached = offline("etags", std::slice::from_ref(&a));
+        let result = block_on(async {
+            for title in ["One", "Two"] {
+                cached
+                    .update_task(
+                        "l",
+                        &Task {
+                            title: title.to_string(),
+                            ..a.clone()
+                        },
+                    )
+                    .await
+                    .unwrap();
+            }
+
+            cached.inner.offline = false;
+            cached.sync().await
+        });
+
+        result.unwrap();
+        assert_eq!(cached.pending_changes(), 0);
+        assert_eq!(titles(cached.get_tasklists()), ["Two"]);
+    }
+
+    #[test]
+    fn replay_stops_while_offline() {
+        let mut cached = offline("offline", &[task("a", "A")]);
+        let result = block_on(async {
+            cached
+                .update_task("l", &task("a", "Renamed"))
+                .await
+                .unwrap();
+            cached.sync().await
+        });
+
+        assert!(cached.is_offline_error(&result.unwrap_err()));
+        assert_eq!(cached.pending_changes(), 1);
+        assert_eq!(cached.get_tasklists()[0].tasks[0].title, "Renamed");
+    }
+
+    /// Online remote with an empty "to" tasklist next to "l".
+    fn lists(tasks: &[Task]) -> Remote {
+        Remote {
+            tasklists: vec![
+                Tasklist::new("l".to_string(), "List".to_string(), tasks),
+                Tasklist::new("to".to_string(), "To".to_string(), &[]),
+            ],
+            ..Default::default()
+        }
+    }
+
+    fn child(id: &str, title: &str, parent: &str) -> Task {
+        Task {
+            parent: Some(parent.to_string()),
+            ..task(id, title)
+        }
+    }
+
+    #[test]
+    fn move_to_list_removes_the_copies_when_it_fails() {
+        let mut remote = lists(&[task("a", "A"), child("b", "rejected", "a")]);
+
+        assert!(block_on(remote.move_task_to_list("l", "a", "to")).is_err());
+        assert_eq!(remote.tasklists[0].tasks.len(), 2);
+        assert!(remote.tasklists[1].tasks.is_empty());
+    }
+
+    #[test]
+    fn move_to_list_copies_a_parent_cycle_once() {
+        let mut remote = lists(&[child("a", "A", "b"), child("b", "B", "a")]);
+
+        block_on(remote.move_task_to_list("l", "a", "to")).unwrap();
+        assert!(remote.tasklists[0].tasks.is_empty());
+        let mut moved = titles(&remote.tasklists[1..]);
+        moved.sort();
+        assert_eq!(moved, vec!["A", "B"]);
+    }
+
+    #[test]
+    fn corrupt_cache_is_moved_aside() {
+        let path = std::env::temp_dir().join("tudo-cache-corrupt.json");
+        let aside = path.with_extension("json.corrupt");
+        let _ = std::fs::remove_file(&aside);
+        std::fs::write(&path, "{\"tasklists\": 1}").unwrap();
+
+        let cached = CachedProvider::new(Remote::default(), path.clone()).unwrap();
+
+        assert!(cached.cache.tasklists.is_empty());
+        assert!(cached.cache.queue.is_empty());
+        assert!(!path.exists());
+        assert_eq!(
+            std::fs::read_to_string(&aside).unwrap(),
+            "{\"tasklists\": 1}"
+        );
+    }
+}
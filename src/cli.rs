@@ -0,0 +1,303 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+
+use crate::{
+    app::{Status, Task, Tasklist},
+    provider::Provider,
+    timestamps::TimestampType,
+    todotxt,
+};
+
+/// Manage your google tasks from the terminal, starts the tui without a command.
+#[derive(Parser, Debug)]
+#[command(version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// List tasks with their ids
+    List {
+        /// Only list this tasklist (title or id)
+        #[arg(short, long)]
+        list: Option<String>,
+        #[arg(short, long, value_enum, default_value_t)]
+        format: Format,
+    },
+    /// Add a new task
+    Add {
+        title: String,
+        /// Due date: YYYY-MM-DD [HH:MM], today, tomorrow or +N days
+        #[arg(short, long)]
+        due: Option<String>,
+        #[arg(short, long)]
+        notes: Option<String>,
+        /// Tasklist to add the task to (title or id), defaults to the first one
+        #[arg(short, long)]
+        list: Option<String>,
+    },
+    /// Mark a task as done
+    Done { id: String },
+    /// Show all details of a task
+    Show {
+        id: String,
+        #[arg(short, long, value_enum, default_value_t)]
+        format: Format,
+    },
+    /// Add all tasks from a todo.txt file, projects become tasklists
+    Import {
+        file: String,
+        /// Account to add the tasklists to, defaults to the first one
+        #[arg(short, long)]
+        account: Option<String>,
+    },
+    /// Print all tasks in the todo.txt format
+    Export,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum Format {
+    /// Human readable
+    #[default]
+    Text,
+    /// Tasklists with their tasks as one json document
+    Json,
+    /// One json object per task
+    Ndjson,
+    /// One tab separated line per task, with a header
+    Tsv,
+}
+
+/// Task together with the tasklist it belongs to, for formats that list tasks on their own.
+#[derive(Serialize)]
+struct TaskEntry<'a> {
+    tasklist_id: &'a str,
+    tasklist: &'a str,
+    #[serde(flatten)]
+    task: &'a Task,
+}
+impl<'a> TaskEntry<'a> {
+    fn new(tasklist: &'a Tasklist, task: &'a Task) -> Self {
+        Self {
+            tasklist_id: &tasklist.id,
+            tasklist: &tasklist.title,
+            task,
+        }
+    }
+}
+
+pub async fn run(command: Command, mut provider: Box<dyn Provider>) -> anyhow::Result<()> {
+    if let Err(err) = provider.sync().await {
+        if !provider.is_offline_error(&err) {
+            return Err(err);
+        }
+        eprintln!("offline, using cached tasks");
+    }
+
+    match command {
+        Command::List { list, format } => {
+            let tasklists = match list {
+                Some(list) => vec![find_tasklist(provider.as_ref(), &list)?],
+                None => provider.get_tasklists().iter().collect(),
+            };
+            let entries = || {
+                tasklists
+                    .iter()
+                    .flat_map(|tasklist| tasklist.tasks.iter().map(|t| TaskEntry::new(tasklist, t)))
+            };
+
+            match format {
+                Format::Text => {
+                    for tasklist in tasklists.iter() {
+                        match provider.account(&tasklist.id) {
+                            Some(account) => println!("# {}: {}", account, tasklist.title),
+                            None => println!("# {}", tasklist.title),
+                        }
+                        for task in tasklist.tasks.iter() {
+                            println!("{}", format_task(task));
+                        }
+                    }
+                }
+                Format::Json => println!("{}", serde_json::to_string_pretty(&tasklists)?),
+                Format::Ndjson => {
+                    for entry in entries() {
+                        println!("{}", serde_json::to_string(&entry)?);
+                    }
+                }
+                Format::Tsv => {
+                    println!("{}", TSV_HEADER);
+                    for entry in entries() {
+                        println!("{}", format_tsv(&entry));
+                    }
+                }
+            }
+        }
+        Command::Add {
+            title,
+            due,
+            notes,
+            list,
+        } => {
+            let tasklist_id = match list {
+                Some(list) => find_tasklist(provider.as_ref(), &list)?,
+                None => provider
+                    .get_nth_tasklist(0)
+                    .ok_or(anyhow::anyhow!("no tasklists"))?,
+            }
+            .id
+            .clone();
+            let due = due
+                .map(|due| {
+                    TimestampType::parse(&due).ok_or(anyhow::anyhow!("invalid due date: {}", due))
+                })
+                .transpose()?;
+
+            let task = Task::new("", Status::Todo, &title, due, notes.as_deref());
+            let id = provider.create_task(&tasklist_id, &task).await?;
+
+            println!("{}", id);
+        }
+        Command::Done { id } => {
+            let (tasklist, task) = find_task(provider.as_ref(), &id)?;
+            let tasklist_id = tasklist.id.clone();
+            let task = Task {
+                status: Status::Done,
+                ..task.clone()
+            };
+
+            provider.update_task(&tasklist_id, &task).await?;
+        }
+        Command::Show { id, format } => {
+            let (tasklist, task) = find_task(provider.as_ref(), &id)?;
+            let entry = TaskEntry::new(tasklist, task);
+
+            match format {
+                Format::Text => print_task(&entry),
+                Format::Json => println!("{}", serde_json::to_string_pretty(&entry)?),
+                Format::Ndjson => println!("{}", serde_json::to_string(&entry)?),
+                Format::Tsv => {
+                    println!("{}", TSV_HEADER);
+                    println!("{}", format_tsv(&entry));
+                }
+            }
+        }
+        Command::Import { file, account } => {
+            let content = std::fs::read_to_string(&file)?;
+
+            for tasklist in todotxt::import(&content) {
+                let existing = provider
+                    .get_tasklists()
+                    .iter()
+                    .filter(|t| account.is_none() || provider.account(&t.id) == account.as_deref())
+                    .find(|t| t.title.eq_ignore_ascii_case(&tasklist.title))
+                    .map(|t| t.id.clone());
+                let tasklist_id = match existing {
+                    Some(id) => id,
+                    None => {
+                        provider
+                            .create_tasklist_in(account.as_deref(), &tasklist.title)
+                            .await?
+                    }
+                };
+
+                for task in tasklist.tasks.iter() {
+                    provider.create_task(&tasklist_id, task).await?;
+                }
+                println!("{}: {} tasks", tasklist.title, tasklist.tasks.len());
+            }
+        }
+        Command::Export => print!("{}", todotxt::export(provider.get_tasklists())),
+    }
+
+    Ok(())
+}
+
+fn print_task(entry: &TaskEntry) {
+    let task = entry.task;
+
+    println!("id:     {}", task.id);
+    println!("list:   {}", entry.tasklist);
+    println!("title:  {}", task.title);
+    println!("status: {}", format_status(&task.status));
+    if let Some(due) = &task.due {
+        println!("due:    {}", due);
+    }
+    if let Some(parent) = &task.parent {
+        println!("parent: {}", parent);
+    }
+    if let Some(notes) = &task.notes {
+        println!("notes:");
+        for line in notes.lines() {
+            println!("  {}", line);
+        }
+    }
+}
+
+fn find_tasklist<'a>(provider: &'a dyn Provider, list: &str) -> anyhow::Result<&'a Tasklist> {
+    provider
+        .get_tasklists()
+        .iter()
+        .find(|t| t.id == list || t.title.eq_ignore_ascii_case(list))
+        .ok_or(anyhow::anyhow!("tasklist {} not found", list))
+}
+
+fn find_task<'a>(provider: &'a dyn Provider, id: &str) -> anyhow::Result<(&'a Tasklist, &'a Task)> {
+    provider
+        .get_tasklists()
+        .iter()
+        .find_map(|tasklist| {
+            tasklist
+                .tasks
+                .iter()
+                .find(|t| t.id == id)
+                .map(|task| (tasklist, task))
+        })
+        .ok_or(anyhow::anyhow!("task {} not found", id))
+}
+
+fn format_status(status: &Status) -> &'static str {
+    match status {
+        Status::Todo => "todo",
+        Status::Done => "done",
+        Status::Unknown => "unknown",
+    }
+}
+
+fn format_task(task: &Task) -> String {
+    let status = match task.status {
+        Status::Todo => "[ ]",
+        Status::Done => "[x]",
+        Status::Unknown => "[?]",
+    };
+    let due = task
+        .due
+        .as_ref()
+        .map(|due| format!(" (due {})", due))
+        .unwrap_or_default();
+
+    format!("{} {}  {}{}", status, task.id, task.title, due)
+}
+
+const TSV_HEADER: &str = "tasklist_id\ttasklist\tid\tstatus\tdue\ttitle\tnotes";
+
+fn format_tsv(entry: &TaskEntry) -> String {
+    let escape = |s: &str| {
+        s.replace('\\', "\\\\")
+            .replace('\t', "\\t")
+            .replace('\n', "\\n")
+    };
+    let task = entry.task;
+
+    [
+        entry.tasklist_id.to_string(),
+        escape(entry.tasklist),
+        task.id.clone(),
+        format_status(&task.status).to_string(),
+        task.due.as_ref().map(|d| d.to_string()).unwrap_or_default(),
+        escape(&task.title),
+        escape(task.notes.as_deref().unwrap_or_default()),
+    ]
+    .join("\t")
+}
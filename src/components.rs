@@ -1,102 +1,598 @@
 use tui::{
     backend::Backend,
-    layout::{Constraint, Direction, Layout},
-    style::{Color, Modifier, Style},
+    layout::{Constraint, Direction, Layout, Rect},
+    style::Style,
     symbols::line,
-    text::{Spans, Text},
-    widgets::{Block, Borders, Cell, Paragraph, Row, Table, Tabs},
+    text::{Span, Spans, Text},
+    widgets::{
+        Block, Borders, Cell, Clear, List, ListItem, ListState, Paragraph, Row, Table, TableState,
+        Tabs, Wrap,
+    },
     Frame,
 };
 
 use crate::{
-    app::{App, Status, Task, Tasklist},
-    timestamps::formatter,
+    app::{App, Mode, Status, Task, TaskRow, Tasklist, View},
+    config::{Filter, Sort, TasklistCfg},
+    form::{Field, Input, Search, TaskForm, TasklistForm, TasklistPicker},
+    notifications::{Notifications, Severity},
+    provider::Conflict,
+    theme::Theme,
+    timestamps::formatter::{self, Urgency},
+    worker::Worker,
 };
 
-fn tasklists<'a, B: Backend>(list_names: &[Tasklist]) -> Tabs<'a> {
-    let tabs = list_names
+/// Tabs of the pinned views and all tasklists, the first tasklist of every account is labeled
+/// with the account's name.
+fn tasklists<'a>(provider: &Worker, theme: &Theme) -> Tabs<'a> {
+    let views = View::ALL
         .iter()
-        .map(|x| Spans::from(x.title.clone()))
+        .map(|view| Spans::from(Span::styled(view.title(), theme.view_tab)));
+
+    let mut previous_account = None;
+    let tabs = views
+        .chain(provider.get_tasklists().iter().map(|x| {
+            let account = provider.account(&x.id);
+            let label = account
+                .filter(|_| account != previous_account)
+                .map(|account| Span::styled(format!("{}: ", account), theme.account));
+            previous_account = account;
+
+            Spans::from(
+                label
+                    .into_iter()
+                    .chain(std::iter::once(Span::raw(x.title.clone())))
+                    .collect::<Vec<_>>(),
+            )
+        }))
         .collect();
 
     Tabs::new(tabs)
         .block(Block::default().borders(Borders::BOTTOM))
-        .style(Style::default().fg(Color::White))
-        .highlight_style(Style::default().fg(Color::Blue))
+        .style(theme.tab)
+        .highlight_style(theme.tab_selected)
         .divider(line::VERTICAL)
 }
 
-fn todos_component<'a, B: Backend>(todos: &[Task]) -> Table<'a> {
+/// Table of tasks, `in_view` swaps the notes for the tasklist each task comes from.
+///
+/// Tasks with changes that are still being saved are marked with a `⋯`.
+fn todos_component<'a>(
+    todos: &[TaskRow],
+    provider: &Worker,
+    in_view: bool,
+    theme: &Theme,
+) -> Table<'a> {
     let todos = todos
         .iter()
-        .map(|x| {
+        .map(|row| {
+            let x = row.task;
+            let marker = match (row.has_children, row.collapsed) {
+                (false, _) => "  ",
+                (true, false) => "▾ ",
+                (true, true) => "▸ ",
+            };
+
             Row::new(vec![
                 match x.status {
                     Status::Todo => Cell::from("☐"),
                     Status::Done => Cell::from("☑"),
                     Status::Unknown => Cell::from("?"),
                 },
-                {
-                    let (str, color) = x
-                        .due
-                        .as_ref()
-                        .map(match x.status {
-                            Status::Todo => formatter::relative,
-                            _ => formatter::absolute,
-                        })
-                        .unwrap_or((String::new(), Color::Reset));
+                match (&x.due, &x.status) {
+                    (None, _) => Cell::from(""),
+                    (Some(due), Status::Todo) => {
+                        let (str, urgency) = formatter::relative(due);
 
-                    Cell::from(str).style(Style::default().fg(color))
+                        Cell::from(str).style(match urgency {
+                            Urgency::Overdue => theme.overdue,
+                            Urgency::Today => theme.due_today,
+                            Urgency::Upcoming => theme.upcoming,
+                        })
+                    }
+                    (Some(due), _) => Cell::from(formatter::absolute(due)),
+                },
+                Cell::from(Spans::from(vec![
+                    Span::raw(format!("{}{}{}", "  ".repeat(row.depth), marker, x.title)),
+                    match provider.is_running(&x.id) {
+                        true => Span::styled(" ⋯", theme.muted),
+                        false => Span::raw(""),
+                    },
+                ])),
+                match in_view {
+                    true => Cell::from(row.tasklist.title.clone()),
+                    false => Cell::from(x.notes.to_owned().unwrap_or_default()),
                 },
-                Cell::from(x.title.to_owned()),
-                Cell::from(x.notes.to_owned().unwrap_or_default()),
             ])
+            .style(match x.status {
+                Status::Done => theme.done,
+                _ => Style::default(),
+            })
         })
         .collect::<Vec<Row>>();
 
     Table::new(todos)
-        .header(Row::new(vec!["", "Due", "Title", "Notes"]))
+        .header(
+            Row::new(vec![
+                "",
+                "Due",
+                "Title",
+                match in_view {
+                    true => "List",
+                    false => "Notes",
+                },
+            ])
+            .style(theme.header),
+        )
         .widths(&[
             Constraint::Length(1),
             Constraint::Length(20),
-            Constraint::Length(20),
+            Constraint::Length(30),
             Constraint::Length(50),
         ])
-        .style(Style::default().fg(Color::White))
-        .highlight_style(
-            Style::default()
-                .add_modifier(Modifier::BOLD)
-                .bg(Color::Black),
-        )
+        .style(theme.text)
+        .highlight_style(theme.selected_row)
+}
+
+/// Describes the view settings that differ from the defaults.
+fn tasklist_cfg(cfg: &TasklistCfg) -> String {
+    let mut settings = Vec::new();
+    if cfg.sort != Sort::Position {
+        settings.push(format!("sorted by {}", cfg.sort));
+    }
+    match cfg.filter {
+        Filter::All => {}
+        Filter::HasDue => settings.push("only with a due date".to_string()),
+        Filter::Overdue => settings.push("only overdue".to_string()),
+    }
+    if !cfg.show_completed {
+        settings.push("completed hidden".to_string());
+    }
+
+    settings.join(", ")
 }
 
-pub fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+/// Latest notification, or what is going on in the selected tasklist.
+fn status_bar<'a>(app: &App, theme: &Theme) -> Paragraph<'a> {
+    if let Some(notification) = app.notifications.current() {
+        return match notification.severity {
+            Severity::Info => Paragraph::new(notification.message.clone()).style(theme.success),
+            Severity::Warning => Paragraph::new(notification.message.clone()).style(theme.warning),
+            Severity::Error => {
+                Paragraph::new(format!("{} (E: details)", notification.message)).style(theme.danger)
+            }
+        };
+    }
+
+    if let Some(deleted) = &app.deleted {
+        Paragraph::new(format!(
+            "Deleted \"{}\", press u to undo",
+            deleted.task.title
+        ))
+        .style(theme.muted)
+    } else if app.provider.running() > 0 {
+        Paragraph::new(format!("Saving changes… ({} left)", app.provider.running()))
+            .style(theme.muted)
+    } else if app.provider.pending_changes() > 0 {
+        Paragraph::new(format!(
+            "Offline, {} changes waiting for sync (r to retry)",
+            app.provider.pending_changes()
+        ))
+        .style(theme.warning)
+    } else if app.active_tasklist().is_some() {
+        Paragraph::new(tasklist_cfg(&app.tasklist_cfg())).style(theme.muted)
+    } else {
+        Paragraph::new("")
+    }
+}
+
+pub fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App, theme: &Theme) {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .margin(1)
-        .constraints([Constraint::Length(2), Constraint::Percentage(100)].as_ref())
+        .constraints(
+            [
+                Constraint::Length(2),
+                Constraint::Min(0),
+                Constraint::Length(1),
+            ]
+            .as_ref(),
+        )
         .split(f.size());
 
-    if let Some(tasklist) = app.active_tasklist() {
-        let tabs = tasklists::<B>(app.provider.get_tasklists()).select(app.active_tasklist);
-        f.render_widget(tabs, chunks[0]);
+    let tabs = tasklists(&app.provider, theme).select(app.active_tasklist);
+    f.render_widget(tabs, chunks[0]);
 
-        match tasklist.is_empty() {
-            false => f.render_stateful_widget(
-                todos_component::<B>(&tasklist.tasks),
-                chunks[1],
-                &mut app.tasks_state,
-            ),
-            true => f.render_widget(
-                Paragraph::new(Text::from("No todos in this list!"))
-                    .style(Style::default().fg(Color::Green)),
-                chunks[1],
-            ),
-        };
+    let rows = app.visible_tasks();
+    let empty = match app.active_view() {
+        _ if app.provider.is_empty() => Some(("No tasklists", theme.warning)),
+        Some(View::Today) => Some(("Nothing due today!", theme.success)),
+        Some(View::Upcoming) => Some(("Nothing coming up!", theme.success)),
+        None if app.active_tasklist().is_some_and(|t| !t.tasks.is_empty()) => {
+            Some(("No todos match the filters", theme.warning))
+        }
+        None => Some(("No todos in this list!", theme.success)),
+    }
+    .filter(|_| rows.is_empty());
+
+    match empty {
+        None => {
+            let table = todos_component(&rows, &app.provider, app.active_view().is_some(), theme);
+            f.render_stateful_widget(table, chunks[1], &mut app.tasks_state)
+        }
+        Some((message, style)) => {
+            f.render_widget(Paragraph::new(Text::from(message)).style(style), chunks[1])
+        }
+    };
+
+    f.render_widget(status_bar(app, theme), chunks[2]);
+
+    match &app.mode {
+        Mode::TaskForm(form) => {
+            let tasklist = app.provider.get_tasklist(&form.tasklist_id);
+            task_form(f, form, tasklist.map(|t| t.title.as_str()), theme)
+        }
+        Mode::ConfirmDelete(_, task) => confirm_delete(f, "Delete task", &task.title, theme),
+        Mode::TasklistForm(form) => tasklist_form(f, form, theme),
+        Mode::ConfirmDeleteTasklist(tasklist) => {
+            confirm_delete(f, "Delete tasklist", &tasklist.title, theme)
+        }
+        Mode::Conflict(conflict) => conflict_component(f, conflict, theme),
+        Mode::MoveToList(picker) => move_to_list(f, picker, &app.provider, theme),
+        Mode::Search(search) => search_component(f, search, &app.search_results(), theme),
+        Mode::ErrorLog(scroll) => error_log(f, &app.notifications, *scroll, theme),
+        Mode::Normal => {}
+    }
+}
+
+fn centered_rect(width: u16, height: u16, r: Rect) -> Rect {
+    let width = width.min(r.width);
+    let height = height.min(r.height);
+
+    Rect::new(
+        r.x + (r.width - width) / 2,
+        r.y + (r.height - height) / 2,
+        width,
+        height,
+    )
+}
+
+fn input_component<'a>(
+    title: &'a str,
+    input: &'a Input,
+    focused: bool,
+    scroll: u16,
+    theme: &Theme,
+) -> Paragraph<'a> {
+    let border_style = if focused {
+        theme.border_focused
     } else {
+        theme.border
+    };
+
+    Paragraph::new(input.value()).scroll((scroll, 0)).block(
+        Block::default()
+            .title(title)
+            .borders(Borders::ALL)
+            .border_style(border_style),
+    )
+}
+
+/// The title of the tasklist is shown as new tasks added in a view can go to any of them.
+fn task_form<B: Backend>(f: &mut Frame<B>, form: &TaskForm, tasklist: Option<&str>, theme: &Theme) {
+    let area = centered_rect(60, 16, f.size());
+    let title = match (form.is_edit(), tasklist) {
+        (true, _) => "Edit task".to_string(),
+        (false, Some(tasklist)) => format!("New task in {}", tasklist),
+        (false, None) => "New task".to_string(),
+    };
+    let block = Block::default().title(title).borders(Borders::ALL);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Length(3),
+                Constraint::Length(6),
+                Constraint::Length(3),
+                Constraint::Length(1),
+            ]
+            .as_ref(),
+        )
+        .split(block.inner(area));
+
+    f.render_widget(Clear, area);
+    f.render_widget(block, area);
+
+    let fields = [
+        (Field::Title, "Title", &form.title),
+        (Field::Notes, "Notes", &form.notes),
+        (Field::Due, "Due (YYYY-MM-DD [HH:MM])", &form.due),
+    ];
+    for (i, (field, title, input)) in fields.into_iter().enumerate() {
+        let focused = form.focus == field;
+        let (column, line) = input.position();
+        let height = chunks[i].height.saturating_sub(2);
+        let scroll = (line as u16).saturating_sub(height.saturating_sub(1));
+
         f.render_widget(
-            Paragraph::new(Text::from("No tasklists")).style(Style::default().fg(Color::Yellow)),
-            chunks[0],
+            input_component(title, input, focused, scroll, theme),
+            chunks[i],
+        );
+
+        if focused {
+            f.set_cursor(
+                chunks[i].x + 1 + column as u16,
+                chunks[i].y + 1 + line as u16 - scroll,
+            );
+        }
+    }
+
+    f.render_widget(
+        Paragraph::new("Tab: next field, Enter: save, Ctrl+s: save from notes, Esc: cancel")
+            .style(theme.muted),
+        chunks[3],
+    );
+}
+
+fn tasklist_form<B: Backend>(f: &mut Frame<B>, form: &TasklistForm, theme: &Theme) {
+    let area = centered_rect(50, 4, f.size());
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Length(1)].as_ref())
+        .split(area);
+
+    let title = if form.is_rename() {
+        "Rename tasklist"
+    } else {
+        "New tasklist"
+    };
+    let (column, _) = form.title.position();
+
+    f.render_widget(Clear, area);
+    f.render_widget(
+        input_component(title, &form.title, true, 0, theme),
+        chunks[0],
+    );
+    f.set_cursor(chunks[0].x + 1 + column as u16, chunks[0].y + 1);
+
+    f.render_widget(
+        Paragraph::new("Enter: save, Esc: cancel").style(theme.muted),
+        chunks[1],
+    );
+}
+
+fn confirm_delete<B: Backend>(f: &mut Frame<B>, title: &str, name: &str, theme: &Theme) {
+    let area = centered_rect(50, 5, f.size());
+
+    f.render_widget(Clear, area);
+    f.render_widget(
+        Paragraph::new(vec![
+            Spans::from(format!("Delete \"{}\"?", name)),
+            Spans::from(""),
+            Spans::from("y: delete, n: cancel"),
+        ])
+        .block(
+            Block::default()
+                .title(title)
+                .borders(Borders::ALL)
+                .border_style(theme.danger),
+        ),
+        area,
+    );
+}
+
+fn search_component<B: Backend>(
+    f: &mut Frame<B>,
+    search: &Search,
+    results: &[(&Tasklist, &Task)],
+    theme: &Theme,
+) {
+    let area = centered_rect(80, 20, f.size());
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Length(3),
+                Constraint::Min(0),
+                Constraint::Length(1),
+            ]
+            .as_ref(),
         )
+        .split(area);
+
+    let rows = results
+        .iter()
+        .map(|(tasklist, task)| {
+            Row::new(vec![
+                Cell::from(task.title.clone()),
+                Cell::from(tasklist.title.clone()).style(theme.muted),
+            ])
+        })
+        .collect::<Vec<Row>>();
+    let mut state = TableState::default();
+    state.select(Some(search.selected).filter(|_| !results.is_empty()));
+    let (column, _) = search.query.position();
+
+    f.render_widget(Clear, area);
+    f.render_widget(
+        input_component("Search", &search.query, true, 0, theme),
+        chunks[0],
+    );
+    f.set_cursor(chunks[0].x + 1 + column as u16, chunks[0].y + 1);
+    f.render_stateful_widget(
+        Table::new(rows)
+            .header(Row::new(vec!["Title", "List"]).style(theme.header))
+            .widths(&[Constraint::Percentage(65), Constraint::Percentage(35)])
+            .block(Block::default().borders(Borders::LEFT | Borders::RIGHT))
+            .highlight_style(theme.selected_row),
+        chunks[1],
+        &mut state,
+    );
+    f.render_widget(
+        Paragraph::new(format!(
+            "{} found, Up/Down: select, Enter: jump, Esc: cancel",
+            results.len()
+        ))
+        .style(theme.muted),
+        chunks[2],
+    );
+}
+
+fn move_to_list<B: Backend>(
+    f: &mut Frame<B>,
+    picker: &TasklistPicker,
+    provider: &Worker,
+    theme: &Theme,
+) {
+    let tasklists = provider.get_tasklists();
+    let area = centered_rect(40, tasklists.len() as u16 + 2, f.size());
+    let items = tasklists
+        .iter()
+        .map(|t| match provider.account(&t.id) {
+            Some(account) => ListItem::new(format!("{}: {}", account, t.title)),
+            None => ListItem::new(t.title.clone()),
+        })
+        .collect::<Vec<ListItem>>();
+    let mut state = ListState::default();
+    state.select(Some(picker.selected));
+
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(
+        List::new(items)
+            .block(
+                Block::default()
+                    .title(format!("Move \"{}\" to", picker.task.title))
+                    .borders(Borders::ALL),
+            )
+            .highlight_style(theme.selected_row),
+        area,
+        &mut state,
+    );
+}
+
+fn error_log<B: Backend>(
+    f: &mut Frame<B>,
+    notifications: &Notifications,
+    scroll: u16,
+    theme: &Theme,
+) {
+    let area = centered_rect(90, 20, f.size());
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)].as_ref())
+        .split(area);
+
+    let mut lines = Vec::new();
+    for notification in notifications.log() {
+        let (severity, style) = match notification.severity {
+            Severity::Info => ("info", theme.success),
+            Severity::Warning => ("warning", theme.warning),
+            Severity::Error => ("error", theme.danger),
+        };
+        lines.push(Spans::from(vec![
+            Span::styled(
+                notification.time.format("%H:%M:%S ").to_string(),
+                theme.muted,
+            ),
+            Span::styled(severity, style),
+        ]));
+        lines.extend(
+            notification
+                .details
+                .lines()
+                .map(|l| Spans::from(l.to_string())),
+        );
+        lines.push(Spans::from(""));
     }
+    if lines.is_empty() {
+        lines.push(Spans::from(Span::styled("No errors", theme.muted)));
+    }
+
+    f.render_widget(Clear, area);
+    f.render_widget(
+        Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .scroll((scroll, 0))
+            .block(Block::default().title("Error log").borders(Borders::ALL)),
+        chunks[0],
+    );
+    f.render_widget(
+        Paragraph::new("j/k: scroll, Esc: close").style(theme.muted),
+        chunks[1],
+    );
+}
+
+fn conflict_fields(task: &Task) -> [(&'static str, String); 4] {
+    [
+        ("Title", task.title.clone()),
+        (
+            "Status",
+            match task.status {
+                Status::Todo => "todo",
+                Status::Done => "done",
+                Status::Unknown => "?",
+            }
+            .to_string(),
+        ),
+        (
+            "Due",
+            task.due.as_ref().map(|d| d.to_string()).unwrap_or_default(),
+        ),
+        (
+            "Notes",
+            task.notes.clone().unwrap_or_default().replace('\n', " "),
+        ),
+    ]
+}
+
+fn conflict_component<B: Backend>(f: &mut Frame<B>, conflict: &Conflict, theme: &Theme) {
+    let area = centered_rect(90, 12, f.size());
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)].as_ref())
+        .split(area);
+
+    let rows = conflict_fields(&conflict.local)
+        .into_iter()
+        .zip(conflict_fields(&conflict.remote))
+        .map(|((name, local), (_, remote))| {
+            let style = if local != remote {
+                theme.warning
+            } else {
+                Style::default()
+            };
+
+            Row::new(vec![name.to_string(), local, remote]).style(style)
+        })
+        .collect::<Vec<Row>>();
+
+    f.render_widget(Clear, area);
+    f.render_widget(
+        Table::new(rows)
+            .header(Row::new(vec![
+                "".to_string(),
+                "Mine".to_string(),
+                format!(
+                    "Theirs (updated {})",
+                    conflict.remote.updated.as_deref().unwrap_or("?")
+                ),
+            ]))
+            .widths(&[
+                Constraint::Length(8),
+                Constraint::Percentage(45),
+                Constraint::Percentage(45),
+            ])
+            .block(
+                Block::default()
+                    .title(conflict.to_string())
+                    .borders(Borders::ALL)
+                    .border_style(theme.danger),
+            ),
+        chunks[0],
+    );
+    f.render_widget(
+        Paragraph::new("l: keep mine, r: keep theirs, e: edit mine on top of theirs")
+            .style(theme.muted),
+        chunks[1],
+    );
 }
@@ -1,11 +1,220 @@
+use std::{collections::HashMap, path::PathBuf};
+
 use envpath::EnvPath;
-use schematic::{Config, ConfigLoader};
+use schematic::{derive_enum, Config, ConfigEnum, ConfigLoader};
+
+use crate::keymap::Action;
+
+derive_enum!(
+    #[derive(ConfigEnum, Default, Copy)]
+    pub enum ProviderKind {
+        #[default]
+        Google,
+        /// Tasks stored in a file in the config dir, no account needed.
+        Local,
+        /// Tasks stored in a todo.txt file.
+        TodoTxt,
+        /// Tasks stored as VTODOs on a CalDAV server.
+        Caldav,
+        /// Microsoft To Do, needs tudo built with the `microsoft` feature.
+        Microsoft,
+    }
+);
+
+derive_enum!(
+    #[derive(ConfigEnum, Default, Copy)]
+    pub enum Sort {
+        /// The order set on the provider.
+        #[default]
+        Position,
+        Due,
+        Title,
+        Status,
+    }
+);
+
+derive_enum!(
+    #[derive(ConfigEnum, Default, Copy)]
+    pub enum Filter {
+        #[default]
+        All,
+        HasDue,
+        Overdue,
+    }
+);
+
+derive_enum!(
+    #[derive(ConfigEnum, Default, Copy)]
+    pub enum ThemePreset {
+        #[default]
+        Dark,
+        Light,
+    }
+);
+
+derive_enum!(
+    /// Styles of the theme that can be changed in the config.
+    #[derive(ConfigEnum, Copy, Hash)]
+    pub enum StyleName {
+        Text,
+        Tab,
+        TabSelected,
+        ViewTab,
+        Account,
+        Header,
+        SelectedRow,
+        Overdue,
+        DueToday,
+        Upcoming,
+        Done,
+        Muted,
+        Border,
+        BorderFocused,
+        Warning,
+        Danger,
+        Success,
+    }
+);
 
 #[derive(Config, Debug)]
 #[config(rename_all = "snake_case")]
 pub struct Cfg {
+    pub provider: ProviderKind,
+    #[setting(default = "client_secret.json")]
+    pub client_secret: String,
+    /// Path of the todo.txt file, defaults to `todo.txt` in the config dir.
+    pub todo_txt: Option<String>,
+    /// Seconds between background refreshes of the tasklists, `0` disables them.
+    #[setting(default = 60)]
+    pub refresh_interval: u64,
+    /// Days ahead shown in the Upcoming view.
+    #[setting(default = 7)]
+    pub upcoming_days: u64,
+    /// Title or id of the tasklist that gets the tasks added in the Today and Upcoming views,
+    /// the one of the selected task when unset.
+    pub default_tasklist: Option<String>,
+    #[setting(nested)]
+    pub caldav: CaldavCfg,
+    #[setting(nested)]
+    pub microsoft: MicrosoftCfg,
+    #[setting(nested)]
+    pub theme: ThemeCfg,
+    /// Key sequences of each action, replacing its default keys.
+    pub keymap: HashMap<Action, Vec<String>>,
+    /// View settings written here by earlier versions, `views.json` in the cache dir wins.
+    pub tasklists: HashMap<String, TasklistCfg>,
+    /// Accounts shown together, the provider settings above are used when there are none.
+    #[setting(nested)]
+    pub accounts: Vec<AccountCfg>,
+}
+
+/// Variant after `value`, wrapping around to the first one.
+pub fn next<T: ConfigEnum + PartialEq>(value: &T) -> T {
+    let mut variants = T::variants();
+    let i = variants
+        .iter()
+        .position(|v| v == value)
+        .map_or(0, |i| i + 1);
+
+    variants.swap_remove(i % variants.len())
+}
+
+#[derive(Config, Clone, Debug)]
+#[config(rename_all = "snake_case")]
+pub struct ThemeCfg {
+    pub preset: ThemePreset,
+    /// Styles put on top of the ones of the preset.
+    pub styles: HashMap<StyleName, StyleCfg>,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct StyleCfg {
+    /// A color name like `light-blue`, a `#rrggbb` hex color or a 0-255 palette index.
+    pub fg: Option<String>,
+    pub bg: Option<String>,
+    /// Added to the modifiers of the preset, e.g. `bold` or `italic`.
+    pub modifiers: Vec<String>,
+}
+
+/// View settings of a tasklist, changed from the tui and kept in the cache dir.
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct TasklistCfg {
+    pub show_completed: bool,
+    pub sort: Sort,
+    pub filter: Filter,
+}
+impl Default for TasklistCfg {
+    fn default() -> Self {
+        Self {
+            show_completed: true,
+            sort: Sort::default(),
+            filter: Filter::default(),
+        }
+    }
+}
+
+/// Provider settings of one account.
+#[derive(Config, Clone, Debug)]
+#[config(rename_all = "snake_case")]
+pub struct AccountCfg {
+    /// Shown in the tabs bar, also names the folder with the account's caches and tokens.
+    pub name: String,
+    pub provider: ProviderKind,
     #[setting(default = "client_secret.json")]
     pub client_secret: String,
+    pub todo_txt: Option<String>,
+    #[setting(nested)]
+    pub caldav: CaldavCfg,
+    #[setting(nested)]
+    #[cfg_attr(not(feature = "microsoft"), allow(dead_code))]
+    pub microsoft: MicrosoftCfg,
+}
+impl AccountCfg {
+    /// Path of `file` in the `dir` (`cfg` or `cache`) of tudo, named accounts get a folder of
+    /// their own so their tokens and caches do not collide.
+    pub fn path(&self, dir: &str, file: &str) -> PathBuf {
+        let mut path = EnvPath::from([format!("$dir: {}", dir).as_str(), "tudo"])
+            .de()
+            .to_path_buf();
+        if !self.name.is_empty() {
+            path.push(&self.name);
+        }
+        path.push(file);
+
+        path
+    }
+
+    /// Path of the todo.txt file, defaults to `todo.txt` in the config dir.
+    pub fn todo_txt_path(&self) -> String {
+        self.todo_txt
+            .clone()
+            .unwrap_or_else(|| self.path("cfg", "todo.txt").to_string_lossy().to_string())
+    }
+}
+
+#[derive(Config, Clone, Debug)]
+#[config(rename_all = "snake_case")]
+pub struct CaldavCfg {
+    /// Url of the calendar home, every calendar in it that holds tasks becomes a tasklist.
+    pub url: String,
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Config, Clone, Debug)]
+#[config(rename_all = "snake_case")]
+#[cfg_attr(not(feature = "microsoft"), allow(dead_code))]
+pub struct MicrosoftCfg {
+    /// Application (client) id of the app registered in Azure, without it requests are not
+    /// authenticated, which is only useful against a mock server.
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    #[setting(default = "common")]
+    pub tenant: String,
+    #[setting(default = "https://graph.microsoft.com/v1.0")]
+    pub base_url: String,
 }
 
 pub fn get_config() -> anyhow::Result<Cfg> {
@@ -15,7 +224,58 @@ pub fn get_config() -> anyhow::Result<Cfg> {
     Ok(result.config)
 }
 
-fn get_config_path() -> std::path::PathBuf {
+/// The configured accounts, or a single unnamed one made of the top level provider settings.
+pub fn get_accounts(cfg: &Cfg) -> anyhow::Result<Vec<AccountCfg>> {
+    if !cfg.accounts.is_empty() {
+        for (i, account) in cfg.accounts.iter().enumerate() {
+            if account.name.is_empty() || account.name.contains('/') {
+                anyhow::bail!("account names can not be empty or contain '/'");
+            }
+            if cfg.accounts[..i].iter().any(|a| a.name == account.name) {
+                anyhow::bail!("account {} is configured twice", account.name);
+            }
+        }
+
+        return Ok(cfg.accounts.clone());
+    }
+
+    Ok(vec![AccountCfg {
+        name: String::new(),
+        provider: cfg.provider,
+        client_secret: cfg.client_secret.clone(),
+        todo_txt: cfg.todo_txt.clone(),
+        caldav: cfg.caldav.clone(),
+        microsoft: cfg.microsoft.clone(),
+    }])
+}
+
+/// View settings of the tasklists by tasklist id, none when they were never changed.
+pub fn load_tasklist_cfgs() -> anyhow::Result<HashMap<String, TasklistCfg>> {
+    match std::fs::read_to_string(get_views_path()) {
+        Ok(content) => Ok(serde_json::from_str(&content)?),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Writes the view settings of all tasklists, they are state of the tui rather than config.
+pub fn save_tasklist_cfgs(tasklists: &HashMap<String, TasklistCfg>) -> anyhow::Result<()> {
+    let path = get_views_path();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_string(tasklists)?)?;
+
+    Ok(())
+}
+
+fn get_views_path() -> PathBuf {
+    EnvPath::from(["$dir: cache", "tudo", "views.json"])
+        .de()
+        .to_path_buf()
+}
+
+fn get_config_path() -> PathBuf {
     EnvPath::from(["$dir: cfg", "tudo", "config.toml"])
         .de()
         .to_path_buf()
@@ -0,0 +1,292 @@
+use crate::{
+    app::{Status, Task, Tasklist},
+    timestamps::TimestampType,
+};
+
+#[derive(Clone, Debug, Default)]
+pub struct Input {
+    value: String,
+    cursor: usize,
+}
+impl Input {
+    pub fn new(value: &str) -> Self {
+        Self {
+            value: value.to_string(),
+            cursor: value.chars().count(),
+        }
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Cursor position as `(column, line)`, both counted in chars.
+    pub fn position(&self) -> (usize, usize) {
+        let before = self.value.chars().take(self.cursor).collect::<String>();
+        let line = before.matches('\n').count();
+        let column = before.chars().rev().take_while(|c| *c != '\n').count();
+
+        (column, line)
+    }
+
+    fn byte_index(&self, cursor: usize) -> usize {
+        self.value
+            .char_indices()
+            .nth(cursor)
+            .map(|(i, _)| i)
+            .unwrap_or(self.value.len())
+    }
+    fn line_lengths(&self) -> Vec<usize> {
+        self.value.split('\n').map(|l| l.chars().count()).collect()
+    }
+    fn line_start(&self, line: usize) -> usize {
+        self.line_lengths().iter().take(line).map(|l| l + 1).sum()
+    }
+
+    pub fn insert(&mut self, c: char) {
+        let i = self.byte_index(self.cursor);
+        self.value.insert(i, c);
+        self.cursor += 1;
+    }
+    pub fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            let i = self.byte_index(self.cursor);
+            self.value.remove(i);
+        }
+    }
+    pub fn delete(&mut self) {
+        if self.cursor < self.value.chars().count() {
+            let i = self.byte_index(self.cursor);
+            self.value.remove(i);
+        }
+    }
+
+    pub fn left(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+    pub fn right(&mut self) {
+        self.cursor = (self.cursor + 1).min(self.value.chars().count());
+    }
+    pub fn home(&mut self) {
+        let (_, line) = self.position();
+        self.cursor = self.line_start(line);
+    }
+    pub fn end(&mut self) {
+        let (_, line) = self.position();
+        self.cursor = self.line_start(line) + self.line_lengths()[line];
+    }
+
+    /// Moves the cursor one line up, returns `false` if it already is on the first line.
+    pub fn up(&mut self) -> bool {
+        let (column, line) = self.position();
+        if line == 0 {
+            return false;
+        }
+
+        self.cursor = self.line_start(line - 1) + column.min(self.line_lengths()[line - 1]);
+        true
+    }
+    /// Moves the cursor one line down, returns `false` if it already is on the last line.
+    pub fn down(&mut self) -> bool {
+        let (column, line) = self.position();
+        let lengths = self.line_lengths();
+        if line + 1 >= lengths.len() {
+            return false;
+        }
+
+        self.cursor = self.line_start(line + 1) + column.min(lengths[line + 1]);
+        true
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TasklistForm {
+    /// Tasklist being renamed, `None` when creating a new one.
+    pub tasklist_id: Option<String>,
+    pub title: Input,
+}
+impl TasklistForm {
+    pub fn rename(tasklist: &Tasklist) -> Self {
+        Self {
+            tasklist_id: Some(tasklist.id.clone()),
+            title: Input::new(&tasklist.title),
+        }
+    }
+
+    pub fn is_rename(&self) -> bool {
+        self.tasklist_id.is_some()
+    }
+
+    pub fn to_title(&self) -> anyhow::Result<String> {
+        match self.title.value().trim() {
+            "" => anyhow::bail!("tasklist title can not be empty"),
+            title => Ok(title.to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TasklistPicker {
+    /// Tasklist the task is moved from.
+    pub tasklist_id: String,
+    pub task: Task,
+    pub selected: usize,
+}
+impl TasklistPicker {
+    pub fn new(tasklist_id: &str, task: &Task) -> Self {
+        Self {
+            tasklist_id: tasklist_id.to_string(),
+            task: task.clone(),
+            selected: 0,
+        }
+    }
+
+    pub fn next(&mut self, len: usize) {
+        if len > 0 {
+            self.selected = (self.selected + 1) % len;
+        }
+    }
+    pub fn previous(&mut self, len: usize) {
+        if len > 0 {
+            self.selected = (self.selected + len - 1) % len;
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Search {
+    pub query: Input,
+    /// Selected result, reset whenever the query changes.
+    pub selected: usize,
+}
+impl Search {
+    pub fn next(&mut self, len: usize) {
+        if len > 0 {
+            self.selected = (self.selected + 1) % len;
+        }
+    }
+    pub fn previous(&mut self, len: usize) {
+        if len > 0 {
+            self.selected = (self.selected + len - 1) % len;
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Field {
+    #[default]
+    Title,
+    Notes,
+    Due,
+}
+impl Field {
+    pub fn next(self) -> Self {
+        match self {
+            Field::Title => Field::Notes,
+            Field::Notes => Field::Due,
+            Field::Due => Field::Title,
+        }
+    }
+    pub fn previous(self) -> Self {
+        match self {
+            Field::Title => Field::Due,
+            Field::Notes => Field::Title,
+            Field::Due => Field::Notes,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TaskForm {
+    /// Tasklist the task is saved to.
+    pub tasklist_id: String,
+    /// Task being edited, `None` when creating a new one.
+    pub task: Option<Task>,
+
+    pub title: Input,
+    pub notes: Input,
+    pub due: Input,
+    pub focus: Field,
+}
+impl TaskForm {
+    pub fn new(tasklist_id: &str) -> Self {
+        Self {
+            tasklist_id: tasklist_id.to_string(),
+            ..Default::default()
+        }
+    }
+    pub fn edit(tasklist_id: &str, task: &Task) -> Self {
+        Self {
+            tasklist_id: tasklist_id.to_string(),
+            task: Some(task.clone()),
+            title: Input::new(&task.title),
+            notes: Input::new(task.notes.as_deref().unwrap_or_default()),
+            due: Input::new(&task.due.as_ref().map(|d| d.to_string()).unwrap_or_default()),
+            focus: Field::Title,
+        }
+    }
+
+    pub fn is_edit(&self) -> bool {
+        self.task.is_some()
+    }
+
+    pub fn focused(&mut self) -> &mut Input {
+        match self.focus {
+            Field::Title => &mut self.title,
+            Field::Notes => &mut self.notes,
+            Field::Due => &mut self.due,
+        }
+    }
+
+    pub fn focus_next(&mut self) {
+        self.focus = self.focus.next();
+    }
+    pub fn focus_previous(&mut self) {
+        self.focus = self.focus.previous();
+    }
+
+    pub fn up(&mut self) {
+        if !self.focused().up() {
+            self.focus_previous();
+        }
+    }
+    pub fn down(&mut self) {
+        if !self.focused().down() {
+            self.focus_next();
+        }
+    }
+
+    /// Enter inserts a line break in the notes and submits from every other field.
+    pub fn wants_newline(&self) -> bool {
+        self.focus == Field::Notes
+    }
+
+    pub fn to_task(&self) -> anyhow::Result<Task> {
+        let title = self.title.value().trim();
+        if title.is_empty() {
+            anyhow::bail!("task title can not be empty");
+        }
+
+        let due = match self.due.value().trim() {
+            "" => None,
+            due => Some(
+                TimestampType::parse(due).ok_or(anyhow::anyhow!("invalid due date: {}", due))?,
+            ),
+        };
+        let notes = match self.notes.value().trim() {
+            "" => None,
+            notes => Some(notes.to_string()),
+        };
+
+        Ok(match &self.task {
+            Some(task) => Task {
+                title: title.to_string(),
+                due,
+                notes,
+                ..task.clone()
+            },
+            None => Task::new("", Status::Todo, title, due, notes.as_deref()),
+        })
+    }
+}
@@ -0,0 +1,63 @@
+/// Scores how well `query` matches `text`, `None` if its chars do not all appear in order.
+///
+/// Matching ignores case, consecutive chars and chars at the start of a word score higher, and
+/// gaps between matched chars lower the score.
+pub fn score(query: &str, text: &str) -> Option<i64> {
+    let query = query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect::<Vec<_>>();
+    if query.is_empty() {
+        return Some(0);
+    }
+
+    let mut score = 0;
+    let mut matched = 0;
+    let mut previous_match: Option<usize> = None;
+    let mut previous_char = None;
+    for (i, c) in text.chars().enumerate() {
+        if matched == query.len() {
+            break;
+        }
+
+        if c.to_lowercase().eq(std::iter::once(query[matched])) {
+            let word_start = previous_char.is_none_or(|p: char| !p.is_alphanumeric());
+            score += match previous_match {
+                Some(p) if p + 1 == i => 8,
+                Some(p) => 1 - (i - p).min(5) as i64,
+                None => 1 - i.min(5) as i64,
+            };
+            if word_start {
+                score += 6;
+            }
+
+            matched += 1;
+            previous_match = Some(i);
+        }
+        previous_char = Some(c);
+    }
+
+    Some(score).filter(|_| matched == query.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn needs_all_chars_in_order() {
+        assert_eq!(score("", "anything"), Some(0));
+        assert!(score("ab", "b a").is_none());
+        assert!(score("abc", "ab").is_none());
+        assert!(score("BUY", "buy milk").is_some());
+    }
+
+    #[test]
+    fn ranks_consecutive_and_word_start_matches_higher() {
+        assert!(score("milk", "buy milk") > score("milk", "m-i-l-k"));
+        assert!(score("bm", "buy milk") > score("bm", "bumper"));
+        assert!(score("t", "task") > score("t", "a task"));
+        assert!(score("t", "a task") > score("t", "a list"));
+    }
+}
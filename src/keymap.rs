@@ -0,0 +1,357 @@
+use std::collections::HashMap;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use schematic::{derive_enum, ConfigEnum};
+
+derive_enum!(
+    /// Everything a key can be bound to in the tasks table.
+    #[derive(ConfigEnum, Copy, Hash)]
+    pub enum Action {
+        Quit,
+        Sync,
+        TasklistsNext,
+        TasklistsPrevious,
+        TasksNext,
+        TasksPrevious,
+        TasksFirst,
+        TasksLast,
+        ToggleTask,
+        AddTask,
+        EditTask,
+        DeleteTask,
+        UndoDelete,
+        MoveDown,
+        MoveUp,
+        Indent,
+        Outdent,
+        MoveToList,
+        Collapse,
+        Expand,
+        Search,
+        ToggleCompleted,
+        NextSort,
+        NextFilter,
+        AddTasklist,
+        RenameTasklist,
+        DeleteTasklist,
+        ErrorLog,
+    }
+);
+
+impl Action {
+    fn default_keys(&self) -> &'static [&'static str] {
+        match self {
+            Action::Quit => &["q"],
+            Action::Sync => &["r"],
+            Action::TasklistsNext => &["l", "right"],
+            Action::TasklistsPrevious => &["h", "left"],
+            Action::TasksNext => &["j", "down"],
+            Action::TasksPrevious => &["k", "up"],
+            Action::TasksFirst => &["gg", "home"],
+            Action::TasksLast => &["G", "end"],
+            Action::ToggleTask => &["enter"],
+            Action::AddTask => &["a"],
+            Action::EditTask => &["e"],
+            Action::DeleteTask => &["d"],
+            Action::UndoDelete => &["u"],
+            Action::MoveDown => &["J"],
+            Action::MoveUp => &["K"],
+            Action::Indent => &[">"],
+            Action::Outdent => &["<"],
+            Action::MoveToList => &["m"],
+            Action::Collapse => &["c"],
+            Action::Expand => &["o"],
+            Action::Search => &["/"],
+            Action::ToggleCompleted => &["H"],
+            Action::NextSort => &["s"],
+            Action::NextFilter => &["f"],
+            Action::AddTasklist => &["A"],
+            Action::RenameTasklist => &["R"],
+            Action::DeleteTasklist => &["D"],
+            Action::ErrorLog => &["E"],
+        }
+    }
+}
+
+/// A key with the modifiers held down, shift is part of the char for char keys.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Chord {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl From<KeyEvent> for Chord {
+    fn from(key: KeyEvent) -> Self {
+        let mut modifiers = key.modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT);
+        if !matches!(key.code, KeyCode::Char(_)) {
+            modifiers |= key.modifiers & KeyModifiers::SHIFT;
+        }
+
+        Self {
+            code: key.code,
+            modifiers,
+        }
+    }
+}
+
+/// Parses a key sequence like `ctrl-x ctrl-s`, `gg` or `shift-tab`.
+///
+/// Chords are separated by spaces, a word that is not a named key is read as one chord per char.
+fn parse_sequence(sequence: &str) -> anyhow::Result<Vec<Chord>> {
+    let mut chords = Vec::new();
+    for word in sequence.split_whitespace() {
+        match parse_chord(word) {
+            Some(chord) => chords.push(chord),
+            None if !word.contains('-') || word == "-" => {
+                chords.extend(word.chars().map(|c| Chord {
+                    code: KeyCode::Char(c),
+                    modifiers: KeyModifiers::NONE,
+                }))
+            }
+            None => anyhow::bail!("unknown key `{}`", word),
+        }
+    }
+    if chords.is_empty() {
+        anyhow::bail!("empty key sequence");
+    }
+
+    Ok(chords)
+}
+
+fn parse_chord(word: &str) -> Option<Chord> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut key = word;
+    while let Some((modifier, rest)) = key.split_once('-').filter(|(_, rest)| !rest.is_empty()) {
+        modifiers |= match modifier.to_lowercase().as_str() {
+            "ctrl" | "c" => KeyModifiers::CONTROL,
+            "alt" | "m" => KeyModifiers::ALT,
+            "shift" => KeyModifiers::SHIFT,
+            _ => return None,
+        };
+        key = rest;
+    }
+
+    let code = match key.to_lowercase().as_str() {
+        _ if key.chars().count() == 1 => KeyCode::Char(key.chars().next()?),
+        "enter" | "return" => KeyCode::Enter,
+        "esc" | "escape" => KeyCode::Esc,
+        "tab" => KeyCode::Tab,
+        "backtab" => KeyCode::BackTab,
+        "space" => KeyCode::Char(' '),
+        "backspace" => KeyCode::Backspace,
+        "delete" | "del" => KeyCode::Delete,
+        "insert" => KeyCode::Insert,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        f => KeyCode::F(f.strip_prefix('f')?.parse().ok()?),
+    };
+
+    // Shifted chars are sent as the uppercase char, shift-tab as a backtab.
+    Some(match code {
+        KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => Chord {
+            code: KeyCode::Char(c.to_ascii_uppercase()),
+            modifiers: modifiers - KeyModifiers::SHIFT,
+        },
+        KeyCode::Tab if modifiers.contains(KeyModifiers::SHIFT) => Chord {
+            code: KeyCode::BackTab,
+            modifiers,
+        },
+        code => Chord { code, modifiers },
+    })
+}
+
+/// Whether one of the sequences starts with the other, so one of them could never be pressed.
+fn overlaps(a: &[Chord], b: &[Chord]) -> bool {
+    a.iter().zip(b).all(|(a, b)| a == b)
+}
+
+/// Key sequences bound to actions, with the keys of an unfinished sequence.
+pub struct Keymap {
+    bindings: Vec<(Vec<Chord>, Action)>,
+    pending: Vec<Chord>,
+}
+
+impl Keymap {
+    /// Builds the keymap from the configured keys of each action, actions left out keep their
+    /// default keys unless those are taken by a configured sequence.
+    pub fn new(keymap: &HashMap<Action, Vec<String>>) -> anyhow::Result<Self> {
+        let mut bindings: Vec<(Vec<Chord>, Action)> = Vec::new();
+        for (action, sequences) in keymap {
+            for sequence in sequences {
+                let chords = parse_sequence(sequence)
+                    .map_err(|err| anyhow::anyhow!("keymap.{}: {}", action, err))?;
+                if let Some((_, other)) = bindings.iter().find(|(b, _)| overlaps(b, &chords)) {
+                    anyhow::bail!(
+                        "keymap.{}: `{}` overlaps with a key of {}",
+                        action,
+                        sequence,
+                        other
+                    );
+                }
+
+                bindings.push((chords, *action));
+            }
+        }
+
+        let defaults = Action::variants()
+            .into_iter()
+            .filter(|action| !keymap.contains_key(action))
+            .flat_map(|action| {
+                action
+                    .default_keys()
+                    .iter()
+                    .map(move |sequence| (parse_sequence(sequence), action))
+            })
+            .collect::<Vec<_>>();
+        for (chords, action) in defaults {
+            let chords = chords?;
+            if !bindings.iter().any(|(b, _)| overlaps(b, &chords)) {
+                bindings.push((chords, action));
+            }
+        }
+
+        Ok(Self {
+            bindings,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Feeds a key press, returning the action once a sequence is complete.
+    ///
+    /// A key that does not continue the pending sequence starts a new one.
+    pub fn press(&mut self, key: KeyEvent) -> Option<Action> {
+        self.pending.push(key.into());
+
+        loop {
+            let matching = self
+                .bindings
+                .iter()
+                .filter(|(b, _)| b.starts_with(&self.pending))
+                .collect::<Vec<_>>();
+
+            match matching.as_slice() {
+                [(b, action)] if b.len() == self.pending.len() => {
+                    let action = *action;
+                    self.pending.clear();
+                    return Some(action);
+                }
+                [] if self.pending.len() > 1 => {
+                    self.pending.drain(..self.pending.len() - 1);
+                }
+                [] => {
+                    self.pending.clear();
+                    return None;
+                }
+                _ => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(keymap: &mut Keymap, keys: &[KeyEvent]) -> Vec<Option<Action>> {
+        keys.iter().map(|key| keymap.press(*key)).collect()
+    }
+
+    fn char(c: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE)
+    }
+
+    fn keymap(bindings: &[(Action, &[&str])]) -> anyhow::Result<Keymap> {
+        Keymap::new(
+            &bindings
+                .iter()
+                .map(|(action, keys)| (*action, keys.iter().map(|k| k.to_string()).collect()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn sequences_wait_for_their_last_key() {
+        let mut keymap = keymap(&[(Action::DeleteTask, &["dd"])]).unwrap();
+
+        assert_eq!(
+            press(&mut keymap, &[char('g'), char('g')]),
+            vec![None, Some(Action::TasksFirst)]
+        );
+        assert_eq!(
+            press(&mut keymap, &[char('d'), char('d')]),
+            vec![None, Some(Action::DeleteTask)]
+        );
+    }
+
+    #[test]
+    fn a_key_that_breaks_a_sequence_starts_a_new_one() {
+        let mut keymap = keymap(&[]).unwrap();
+
+        assert_eq!(
+            press(&mut keymap, &[char('g'), char('j')]),
+            vec![None, Some(Action::TasksNext)]
+        );
+        assert_eq!(
+            press(&mut keymap, &[char('g'), char('x'), char('G')]),
+            vec![None, None, Some(Action::TasksLast)]
+        );
+    }
+
+    #[test]
+    fn modifiers_are_part_of_the_chord() {
+        let mut keymap = keymap(&[
+            (Action::Sync, &["ctrl-s"]),
+            (Action::MoveUp, &["shift-k"]),
+            (Action::TasksPrevious, &["shift-tab"]),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            press(
+                &mut keymap,
+                &[
+                    KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL),
+                    char('s'),
+                    KeyEvent::new(KeyCode::Char('K'), KeyModifiers::SHIFT),
+                    KeyEvent::new(KeyCode::BackTab, KeyModifiers::SHIFT),
+                ]
+            ),
+            vec![
+                Some(Action::Sync),
+                Some(Action::NextSort),
+                Some(Action::MoveUp),
+                Some(Action::TasksPrevious),
+            ]
+        );
+    }
+
+    #[test]
+    fn configured_keys_replace_defaults_they_overlap() {
+        let mut keymap = keymap(&[(Action::Quit, &["j"])]).unwrap();
+
+        assert_eq!(
+            press(
+                &mut keymap,
+                &[char('j'), KeyEvent::new(KeyCode::Down, KeyModifiers::NONE)]
+            ),
+            vec![Some(Action::Quit), Some(Action::TasksNext)]
+        );
+    }
+
+    #[test]
+    fn overlapping_or_unknown_keys_are_rejected() {
+        let err = keymap(&[(Action::TasksFirst, &["g"]), (Action::TasksLast, &["gg"])])
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("overlaps"), "{}", err);
+
+        assert!(keymap(&[(Action::Sync, &["ctrl-nope"])]).is_err());
+        assert!(keymap(&[(Action::Sync, &[" "])]).is_err());
+    }
+}
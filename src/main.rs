@@ -1,22 +1,102 @@
+mod accounts;
 mod app;
+mod cache;
+#[path = "providers/caldav.rs"]
+mod caldav;
+mod cli;
 mod components;
 mod config;
+mod form;
+mod fuzzy;
 #[path = "providers/google.rs"]
 mod google;
+mod keymap;
+#[path = "providers/local.rs"]
+mod local;
+#[cfg(feature = "microsoft")]
+#[path = "providers/microsoft.rs"]
+mod microsoft;
+mod notifications;
+mod oauth;
 mod provider;
 mod setup;
+mod terminal;
+mod theme;
 mod timestamps;
+#[path = "providers/todotxt.rs"]
+mod todotxt;
+mod worker;
 
 use std::time::Duration;
 
+use clap::Parser;
+
 extern crate google_tasks1 as tasks1;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let cli = cli::Cli::parse();
     let cfg = config::get_config()?;
 
-    let app = google::setup(&cfg).await?;
-    setup::run(Duration::from_millis(250), app).await?;
+    let configured = config::get_accounts(&cfg)?;
+    let single = configured.len() == 1;
+    // An account that can not log in or sync is left out instead of keeping the others hidden.
+    let mut accounts = Vec::new();
+    let mut failed = Vec::new();
+    for account in configured {
+        match setup_provider(&account).await {
+            Ok(provider) => accounts.push((account.name.clone(), provider)),
+            Err(err) if !single => {
+                failed.push(err.context(format!("account {} is not shown", account.name)))
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    if accounts.is_empty() {
+        return Err(failed.remove(0));
+    }
+    let provider: Box<dyn provider::Provider> = match single {
+        true => accounts.remove(0).1,
+        false => Box::new(accounts::Accounts::new(accounts)),
+    };
+
+    match cli.command {
+        Some(command) => {
+            for err in failed {
+                eprintln!("{:#}", err);
+            }
+            cli::run(command, provider).await?
+        }
+        None => {
+            setup::run(
+                Duration::from_millis(250),
+                Duration::from_secs(cfg.refresh_interval),
+                provider,
+                &cfg,
+                keymap::Keymap::new(&cfg.keymap)?,
+                theme::Theme::new(&cfg.theme)?,
+                failed,
+            )
+            .await?
+        }
+    }
 
     Ok(())
 }
+
+async fn setup_provider(
+    account: &config::AccountCfg,
+) -> anyhow::Result<Box<dyn provider::Provider>> {
+    Ok(match account.provider {
+        config::ProviderKind::Google => Box::new(google::setup(account).await?),
+        config::ProviderKind::Local => Box::new(local::setup(account)?),
+        config::ProviderKind::TodoTxt => Box::new(todotxt::setup(&account.todo_txt_path())?),
+        config::ProviderKind::Caldav => Box::new(caldav::setup(account).await?),
+        #[cfg(feature = "microsoft")]
+        config::ProviderKind::Microsoft => Box::new(microsoft::setup(account).await?),
+        #[cfg(not(feature = "microsoft"))]
+        config::ProviderKind::Microsoft => {
+            anyhow::bail!("tudo was built without the microsoft feature")
+        }
+    })
+}
@@ -0,0 +1,93 @@
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local};
+
+/// Entries kept in the log, older ones are dropped.
+const LOG_SIZE: usize = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+impl Severity {
+    /// How long a notification stays in the status bar.
+    fn timeout(self) -> Duration {
+        match self {
+            Severity::Info => Duration::from_secs(3),
+            Severity::Warning => Duration::from_secs(6),
+            Severity::Error => Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Notification {
+    pub severity: Severity,
+    /// Shown in the status bar.
+    pub message: String,
+    /// Full error with its causes, shown in the error log.
+    pub details: String,
+    pub time: DateTime<Local>,
+    shown_at: Instant,
+}
+
+/// Notifications shown in the status bar, with a log of the warnings and errors.
+#[derive(Debug, Default)]
+pub struct Notifications {
+    current: Option<Notification>,
+    log: Vec<Notification>,
+}
+
+impl Notifications {
+    pub fn info(&mut self, message: impl Into<String>) {
+        self.push(Severity::Info, message.into(), String::new());
+    }
+    pub fn warning(&mut self, message: impl Into<String>) {
+        let message = message.into();
+        self.push(Severity::Warning, message.clone(), message);
+    }
+    pub fn error(&mut self, err: &anyhow::Error) {
+        self.push(Severity::Error, err.to_string(), format!("{:?}", err));
+    }
+
+    fn push(&mut self, severity: Severity, message: String, details: String) {
+        let notification = Notification {
+            severity,
+            message,
+            details,
+            time: Local::now(),
+            shown_at: Instant::now(),
+        };
+
+        if severity != Severity::Info {
+            if self.log.len() >= LOG_SIZE {
+                self.log.remove(0);
+            }
+            self.log.push(notification.clone());
+        }
+        // Something like "Synced" does not cover a warning or error that is still shown.
+        if severity == Severity::Info
+            && self.current().is_some_and(|n| n.severity != Severity::Info)
+        {
+            return;
+        }
+        self.current = Some(notification);
+    }
+
+    /// Notification to show in the status bar, until it times out.
+    pub fn current(&self) -> Option<&Notification> {
+        self.current
+            .as_ref()
+            .filter(|n| n.shown_at.elapsed() < n.severity.timeout())
+    }
+    pub fn dismiss(&mut self) {
+        self.current = None;
+    }
+
+    /// Logged warnings and errors, newest first.
+    pub fn log(&self) -> impl Iterator<Item = &Notification> {
+        self.log.iter().rev()
+    }
+}
@@ -0,0 +1,41 @@
+use std::path::PathBuf;
+
+use hyper::client::HttpConnector;
+use hyper_rustls::HttpsConnector;
+use tasks1::oauth2::{
+    authenticator::Authenticator, ApplicationSecret, InstalledFlowAuthenticator,
+    InstalledFlowReturnMethod,
+};
+
+/// Logs in through the browser, tokens are kept in `token_cache` unless its folder can not be
+/// created.
+pub async fn login(
+    secret: ApplicationSecret,
+    token_cache: PathBuf,
+) -> anyhow::Result<Authenticator<HttpsConnector<HttpConnector>>> {
+    let token_cache = if token_cache.exists() {
+        Some(token_cache)
+    } else {
+        token_cache.parent().and_then(|prefix| {
+            std::fs::create_dir_all(prefix)
+                .ok()
+                .map(|_| token_cache.clone())
+        })
+    };
+
+    let auth = match token_cache {
+        Some(token_cache) => {
+            InstalledFlowAuthenticator::builder(secret, InstalledFlowReturnMethod::HTTPRedirect)
+                .persist_tokens_to_disk(token_cache)
+                .build()
+                .await?
+        }
+        None => {
+            InstalledFlowAuthenticator::builder(secret, InstalledFlowReturnMethod::HTTPRedirect)
+                .build()
+                .await?
+        }
+    };
+
+    Ok(auth)
+}
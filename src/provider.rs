@@ -1,10 +1,21 @@
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    pin::Pin,
+};
+
 use crate::app::{Task, Tasklist};
 
+/// Fetches fresh tasklists without borrowing the provider, so it can run in the background.
+pub type Loader = Box<
+    dyn Fn() -> Pin<Box<dyn Future<Output = anyhow::Result<Vec<Tasklist>>> + Send>> + Send + Sync,
+>;
+
 #[async_trait::async_trait]
-pub trait Provider {
+pub trait Provider: Send {
     fn get_tasklists(&self) -> &Vec<Tasklist>;
-    fn len(&self) -> usize {
-        self.get_tasklists().len()
+    fn is_empty(&self) -> bool {
+        self.get_tasklists().is_empty()
     }
 
     fn get_tasklist(&self, tasklist_id: &str) -> Option<&Tasklist> {
@@ -18,8 +29,155 @@ pub trait Provider {
         self.get_tasklist(tasklist_id)
             .and_then(|t| t.tasks.iter().find(|t| t.id == task_id))
     }
+    /// Name of the account the tasklist belongs to, when tasklists of several are shown.
+    fn account(&self, _tasklist_id: &str) -> Option<&str> {
+        None
+    }
+    /// Reloads all tasklists from the source of truth.
+    async fn sync(&mut self) -> anyhow::Result<()>;
+    /// Whether the error means the provider could not be reached, as opposed to a rejected request.
+    fn is_offline_error(&self, _err: &anyhow::Error) -> bool {
+        false
+    }
+    fn loader(&self) -> Option<Loader> {
+        None
+    }
+    /// Like `loader`, but only resolves once the tasklists changed outside of tudo.
+    fn watcher(&self) -> Option<Loader> {
+        None
+    }
+    /// Replaces the tasklists with ones fetched by the `loader` or `watcher`.
+    fn set_tasklists(&mut self, _tasklists: Vec<Tasklist>) {}
+    /// Number of changes made offline that still wait for a successful sync.
+    fn pending_changes(&self) -> usize {
+        0
+    }
+
+    /// Creates the tasklist and returns its new id.
+    async fn create_tasklist(&mut self, title: &str) -> anyhow::Result<String>;
+    /// Creates the tasklist in the named account, providers of a single account ignore it.
+    async fn create_tasklist_in(
+        &mut self,
+        _account: Option<&str>,
+        title: &str,
+    ) -> anyhow::Result<String> {
+        self.create_tasklist(title).await
+    }
+    async fn rename_tasklist(&mut self, tasklist_id: &str, title: &str) -> anyhow::Result<()>;
+    async fn delete_tasklist(&mut self, tasklist_id: &str) -> anyhow::Result<()>;
+
     async fn update_task(&mut self, tasklist_id: &str, task: &Task) -> anyhow::Result<()>;
+    /// Creates the task (under `task.parent` if set) and returns its new id.
+    async fn create_task(&mut self, tasklist_id: &str, task: &Task) -> anyhow::Result<String>;
+    async fn delete_task(&mut self, tasklist_id: &str, task_id: &str) -> anyhow::Result<()>;
+    /// Moves the task under `parent` (root for `None`), right after `previous` (first for `None`).
+    async fn move_task(
+        &mut self,
+        tasklist_id: &str,
+        task_id: &str,
+        parent: Option<&str>,
+        previous: Option<&str>,
+    ) -> anyhow::Result<()>;
+
+    /// Recreates the task with all its subtasks in another tasklist and deletes the originals.
+    async fn move_task_to_list(
+        &mut self,
+        from_tasklist_id: &str,
+        task_id: &str,
+        to_tasklist_id: &str,
+    ) -> anyhow::Result<()> {
+        let tasklist = self.get_tasklist(from_tasklist_id).ok_or(anyhow::anyhow!(
+            "tasklist with id {} not found",
+            from_tasklist_id
+        ))?;
+        let task = self
+            .get_task(from_tasklist_id, task_id)
+            .ok_or(anyhow::anyhow!("task with id {} not found", task_id))?;
+
+        // Parents come before their children and siblings in reverse, as every insert
+        // places the task first among its siblings.
+        let mut tasks = Vec::new();
+        let mut visited = HashSet::new();
+        let mut stack = vec![task];
+        while let Some(task) = stack.pop() {
+            if !visited.insert(task.id.clone()) {
+                continue;
+            }
+            tasks.push(task.clone());
+            stack.extend(tasklist.children(Some(&task.id)));
+        }
+
+        let mut ids = HashMap::new();
+        let mut created = Vec::new();
+        let mut result = Ok(());
+        for task in tasks.iter() {
+            let parent = task.parent.as_ref().and_then(|p| ids.get(p)).cloned();
+            let task = Task {
+                parent,
+                ..task.clone()
+            };
+            match self.create_task(to_tasklist_id, &task).await {
+                Ok(id) => {
+                    ids.insert(task.id.clone(), id.clone());
+                    created.push(id);
+                }
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
+
+        let mut deleted = 0;
+        if result.is_ok() {
+            for task in tasks.iter().rev() {
+                if let Err(err) = self.delete_task(from_tasklist_id, &task.id).await {
+                    result = Err(err);
+                    break;
+                }
+                deleted += 1;
+            }
+        }
+
+        // Copies are only removed again while all originals still exist, so nothing is lost.
+        if let Err(err) = result {
+            if deleted > 0 {
+                return Err(err.context("some of the moved tasks are still in the old tasklist"));
+            }
+            for id in created.iter().rev() {
+                // The error of the move is the one worth showing.
+                let _ = self.delete_task(to_tasklist_id, id).await;
+            }
+            return Err(err);
+        }
+
+        Ok(())
+    }
+}
+
+/// Returned by `update_task` when the task was changed elsewhere since it was loaded.
+#[derive(Clone, Debug)]
+pub struct Conflict {
+    pub tasklist_id: String,
+    pub local: Task,
+    pub remote: Task,
+}
+impl std::fmt::Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "task \"{}\" was changed elsewhere", self.remote.title)
+    }
+}
+impl std::error::Error for Conflict {}
+
+/// Returned by `sync` when changes made offline ran into conflicts, each waits for the prompt.
+#[derive(Clone, Debug)]
+pub struct Conflicts(pub Vec<Conflict>);
+impl std::fmt::Display for Conflicts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} changes were made elsewhere too", self.0.len())
+    }
 }
+impl std::error::Error for Conflicts {}
 
 #[async_trait::async_trait]
 impl std::fmt::Debug for dyn Provider {
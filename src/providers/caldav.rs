@@ -0,0 +1,1063 @@
+use std::collections::HashMap;
+
+use base64::Engine;
+use chrono::{Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use hyper::{client::HttpConnector, Body, Method, Request, StatusCode};
+use hyper_rustls::HttpsConnector;
+use quick_xml::events::Event;
+
+use crate::{
+    app::{Status, Task, Tasklist},
+    cache::CachedProvider,
+    config::{AccountCfg, CaldavCfg},
+    provider::{Conflict, Provider},
+    timestamps::TimestampType,
+};
+
+/// A VTODO resource as stored on the server.
+#[derive(Clone, Debug)]
+struct Resource {
+    href: String,
+    etag: Option<String>,
+    ics: String,
+}
+
+/// Entry of a WebDAV multistatus response.
+#[derive(Debug, Default)]
+struct Response {
+    href: String,
+    displayname: Option<String>,
+    etag: Option<String>,
+    calendar_data: Option<String>,
+    is_calendar: bool,
+    supports_vtodo: bool,
+}
+
+/// Talks CalDAV to a server, mapping calendar collections that hold VTODOs to tasklists.
+pub struct CaldavProvider {
+    client: hyper::Client<HttpsConnector<HttpConnector>>,
+    /// Url of the calendar home set, new tasklists are created in it.
+    url: hyper::Uri,
+    authorization: String,
+
+    tasklists: Vec<Tasklist>,
+    /// Resources by tasklist id and task id (the VTODO `UID`).
+    resources: HashMap<(String, String), Resource>,
+}
+
+impl CaldavProvider {
+    fn new(cfg: &CaldavCfg) -> anyhow::Result<Self> {
+        let url = match cfg.url.ends_with('/') {
+            true => cfg.url.clone(),
+            false => format!("{}/", cfg.url),
+        };
+        let credentials = format!("{}:{}", cfg.username, cfg.password);
+
+        Ok(Self {
+            client: hyper::Client::builder().build(
+                hyper_rustls::HttpsConnectorBuilder::new()
+                    .with_native_roots()
+                    .https_or_http()
+                    .enable_http1()
+                    .build(),
+            ),
+            url: url.parse()?,
+            authorization: format!(
+                "Basic {}",
+                base64::engine::general_purpose::STANDARD.encode(credentials)
+            ),
+            tasklists: Vec::new(),
+            resources: HashMap::new(),
+        })
+    }
+
+    /// Resolves a href returned by the server against the configured url.
+    fn absolute(&self, href: &str) -> String {
+        match (self.url.scheme_str(), self.url.authority()) {
+            (Some(scheme), Some(authority)) if href.starts_with('/') => {
+                format!("{}://{}{}", scheme, authority, href)
+            }
+            _ => href.to_string(),
+        }
+    }
+
+    async fn request(
+        &self,
+        method: &str,
+        href: &str,
+        headers: &[(&str, &str)],
+        body: String,
+    ) -> anyhow::Result<(StatusCode, Option<String>, String)> {
+        let mut request = Request::builder()
+            .method(Method::from_bytes(method.as_bytes())?)
+            .uri(self.absolute(href))
+            .header("Authorization", &self.authorization);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+
+        let response = self.client.request(request.body(Body::from(body))?).await?;
+        let status = response.status();
+        let etag = response
+            .headers()
+            .get("ETag")
+            .and_then(|e| e.to_str().ok())
+            .map(|e| e.to_string());
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+
+        Ok((status, etag, String::from_utf8_lossy(&body).to_string()))
+    }
+
+    async fn expect(
+        &self,
+        method: &str,
+        href: &str,
+        headers: &[(&str, &str)],
+        body: String,
+    ) -> anyhow::Result<(Option<String>, String)> {
+        let (status, etag, body) = self.request(method, href, headers, body).await?;
+        if !status.is_success() {
+            anyhow::bail!("{} {} failed with {}", method, href, status);
+        }
+
+        Ok((etag, body))
+    }
+
+    async fn load_tasklists(&mut self) -> anyhow::Result<()> {
+        let (_, body) = self
+            .expect(
+                "PROPFIND",
+                &self.url.to_string(),
+                &[("Depth", "1"), ("Content-Type", "application/xml")],
+                PROPFIND_CALENDARS.to_string(),
+            )
+            .await?;
+
+        let mut tasklists = Vec::new();
+        let mut resources = HashMap::new();
+        for calendar in parse_multistatus(&body)?
+            .into_iter()
+            .filter(|r| r.is_calendar && r.supports_vtodo)
+        {
+            let (tasks, calendar_resources) = self.load_tasks(&calendar.href).await?;
+            let title = calendar
+                .displayname
+                .clone()
+                .unwrap_or_else(|| calendar.href.clone());
+
+            tasklists.push(Tasklist::new(calendar.href.clone(), title, &tasks));
+            resources.extend(
+                calendar_resources
+                    .into_iter()
+                    .map(|(id, r)| ((calendar.href.clone(), id), r)),
+            );
+        }
+
+        self.tasklists = tasklists;
+        self.resources = resources;
+
+        Ok(())
+    }
+
+    async fn load_tasks(
+        &self,
+        tasklist_id: &str,
+    ) -> anyhow::Result<(Vec<Task>, Vec<(String, Resource)>)> {
+        let (_, body) = self
+            .expect(
+                "REPORT",
+                tasklist_id,
+                &[("Depth", "1"), ("Content-Type", "application/xml")],
+                REPORT_VTODOS.to_string(),
+            )
+            .await?;
+
+        let mut tasks = Vec::new();
+        let mut resources = Vec::new();
+        for response in parse_multistatus(&body)? {
+            if let Some(ics) = response.calendar_data {
+                if let Some(task) = ics_to_task(&ics, response.etag.clone()) {
+                    resources.push((
+                        task.id.clone(),
+                        Resource {
+                            href: response.href,
+                            etag: response.etag,
+                            ics,
+                        },
+                    ));
+                    tasks.push(task);
+                }
+            }
+        }
+
+        Ok((tasks, resources))
+    }
+
+    async fn reload_tasklist(&mut self, tasklist_id: &str) -> anyhow::Result<()> {
+        let (tasks, resources) = self.load_tasks(tasklist_id).await?;
+
+        self.resources.retain(|(list, _), _| list != tasklist_id);
+        self.resources.extend(
+            resources
+                .into_iter()
+                .map(|(id, r)| ((tasklist_id.to_string(), id), r)),
+        );
+        if let Some(t) = self.tasklists.iter_mut().find(|t| t.id == tasklist_id) {
+            t.tasks = tasks;
+        }
+
+        Ok(())
+    }
+
+    fn resource(&self, tasklist_id: &str, task_id: &str) -> anyhow::Result<&Resource> {
+        self.resources
+            .get(&(tasklist_id.to_string(), task_id.to_string()))
+            .ok_or(anyhow::anyhow!("task with id {} not found", task_id))
+    }
+
+    /// Writes the resource, only if nobody changed it since it was loaded.
+    async fn put(&self, tasklist_id: &str, resource: &Resource, task: &Task) -> anyhow::Result<()> {
+        let ics = update_ics(&resource.ics, task)?;
+        let mut headers = vec![("Content-Type", "text/calendar; charset=utf-8")];
+        if let Some(etag) = &resource.etag {
+            headers.push(("If-Match", etag));
+        }
+
+        let (status, _, _) = self.request("PUT", &resource.href, &headers, ics).await?;
+        if status == StatusCode::PRECONDITION_FAILED {
+            let (etag, ics) = self
+                .expect("GET", &resource.href, &[], String::new())
+                .await?;
+            let remote = ics_to_task(&ics, etag)
+                .ok_or(anyhow::anyhow!("task {} can not be parsed", task.id))?;
+
+            return Err(Conflict {
+                tasklist_id: tasklist_id.to_string(),
+                local: task.clone(),
+                remote,
+            }
+            .into());
+        }
+        if !status.is_success() {
+            anyhow::bail!("PUT {} failed with {}", resource.href, status);
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Provider for CaldavProvider {
+    fn get_tasklists(&self) -> &Vec<Tasklist> {
+        &self.tasklists
+    }
+
+    async fn sync(&mut self) -> anyhow::Result<()> {
+        self.load_tasklists().await
+    }
+
+    fn is_offline_error(&self, err: &anyhow::Error) -> bool {
+        err.downcast_ref::<hyper::Error>()
+            .is_some_and(|e| e.is_connect() || e.is_timeout())
+    }
+
+    async fn create_tasklist(&mut self, title: &str) -> anyhow::Result<String> {
+        let href = format!("{}{}/", self.url.path(), new_uid());
+        self.expect(
+            "MKCALENDAR",
+            &href,
+            &[("Content-Type", "application/xml")],
+            mkcalendar(title),
+        )
+        .await?;
+
+        self.tasklists
+            .push(Tasklist::new(href.clone(), title.to_string(), &[]));
+
+        Ok(href)
+    }
+
+    async fn rename_tasklist(&mut self, tasklist_id: &str, title: &str) -> anyhow::Result<()> {
+        self.expect(
+            "PROPPATCH",
+            tasklist_id,
+            &[("Content-Type", "application/xml")],
+            proppatch_displayname(title),
+        )
+        .await?;
+
+        if let Some(t) = self.tasklists.iter_mut().find(|t| t.id == tasklist_id) {
+            t.title = title.to_string();
+        }
+
+        Ok(())
+    }
+
+    async fn delete_tasklist(&mut self, tasklist_id: &str) -> anyhow::Result<()> {
+        self.expect("DELETE", tasklist_id, &[], String::new())
+            .await?;
+
+        self.tasklists.retain(|t| t.id != tasklist_id);
+        self.resources.retain(|(list, _), _| list != tasklist_id);
+
+        Ok(())
+    }
+
+    async fn update_task(&mut self, tasklist_id: &str, task: &Task) -> anyhow::Result<()> {
+        let resource = self.resource(tasklist_id, &task.id)?.clone();
+        // A task forced over a conflict carries the etag of the remote version.
+        let resource = Resource {
+            etag: task.etag.clone().or(resource.etag),
+            ..resource
+        };
+        self.put(tasklist_id, &resource, task).await?;
+
+        self.reload_tasklist(tasklist_id).await
+    }
+
+    async fn create_task(&mut self, tasklist_id: &str, task: &Task) -> anyhow::Result<String> {
+        let uid = new_uid();
+        let ics = update_ics(
+            &new_ics(&uid),
+            &Task {
+                id: uid.clone(),
+                ..task.clone()
+            },
+        )?;
+
+        let href = format!("{}{}.ics", tasklist_id, uid);
+        let (etag, _) = self
+            .expect(
+                "PUT",
+                &href,
+                &[
+                    ("Content-Type", "text/calendar; charset=utf-8"),
+                    ("If-None-Match", "*"),
+                ],
+                ics.clone(),
+            )
+            .await?;
+
+        // The server stores what was sent, so the tasklist is not loaded again.
+        let created = ics_to_task(&ics, etag.clone())
+            .ok_or(anyhow::anyhow!("task {} can not be parsed", uid))?;
+        if let Some(t) = self.tasklists.iter_mut().find(|t| t.id == tasklist_id) {
+            t.tasks.push(created);
+        }
+        self.resources.insert(
+            (tasklist_id.to_string(), uid.clone()),
+            Resource { href, etag, ics },
+        );
+
+        Ok(uid)
+    }
+
+    async fn delete_task(&mut self, tasklist_id: &str, task_id: &str) -> anyhow::Result<()> {
+        let resource = self.resource(tasklist_id, task_id)?.clone();
+        let mut headers = Vec::new();
+        if let Some(etag) = &resource.etag {
+            headers.push(("If-Match", etag.as_str()));
+        }
+
+        self.expect("DELETE", &resource.href, &headers, String::new())
+            .await?;
+
+        if let Some(t) = self.tasklists.iter_mut().find(|t| t.id == tasklist_id) {
+            t.tasks.retain(|t| t.id != task_id);
+        }
+        self.resources
+            .remove(&(tasklist_id.to_string(), task_id.to_string()));
+
+        Ok(())
+    }
+
+    async fn move_task(
+        &mut self,
+        tasklist_id: &str,
+        task_id: &str,
+        parent: Option<&str>,
+        previous: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let tasklist = self.get_tasklist(tasklist_id).ok_or(anyhow::anyhow!(
+            "tasklist with id {} not found",
+            tasklist_id
+        ))?;
+
+        let mut order = tasklist
+            .children(parent)
+            .into_iter()
+            .filter(|t| t.id != task_id)
+            .map(|t| t.id.clone())
+            .collect::<Vec<_>>();
+        let i = previous
+            .and_then(|previous| order.iter().position(|id| id == previous))
+            .map_or(0, |i| i + 1);
+        order.insert(i, task_id.to_string());
+
+        // Sort orders are only written for tasks whose place actually changed.
+        let mut changed = Vec::new();
+        for (i, id) in order.iter().enumerate() {
+            let task = tasklist
+                .tasks
+                .iter()
+                .find(|t| &t.id == id)
+                .ok_or(anyhow::anyhow!("task with id {} not found", id))?;
+            let position = Some(format_position(i as i64));
+            let new_parent = parent.map(|p| p.to_string());
+
+            if task.position != position || task.parent != new_parent {
+                changed.push(Task {
+                    parent: new_parent,
+                    position,
+                    ..task.clone()
+                });
+            }
+        }
+
+        for task in changed.iter() {
+            let resource = self.resource(tasklist_id, &task.id)?.clone();
+            self.put(tasklist_id, &resource, task).await?;
+        }
+
+        self.reload_tasklist(tasklist_id).await
+    }
+}
+
+const PROPFIND_CALENDARS: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">
+  <d:prop>
+    <d:resourcetype/>
+    <d:displayname/>
+    <c:supported-calendar-component-set/>
+  </d:prop>
+</d:propfind>"#;
+
+const REPORT_VTODOS: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<c:calendar-query xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">
+  <d:prop>
+    <d:getetag/>
+    <c:calendar-data/>
+  </d:prop>
+  <c:filter>
+    <c:comp-filter name="VCALENDAR">
+      <c:comp-filter name="VTODO"/>
+    </c:comp-filter>
+  </c:filter>
+</c:calendar-query>"#;
+
+fn mkcalendar(title: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<c:mkcalendar xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">
+  <d:set>
+    <d:prop>
+      <d:displayname>{}</d:displayname>
+      <c:supported-calendar-component-set>
+        <c:comp name="VTODO"/>
+      </c:supported-calendar-component-set>
+    </d:prop>
+  </d:set>
+</c:mkcalendar>"#,
+        quick_xml::escape::escape(title)
+    )
+}
+
+fn proppatch_displayname(title: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propertyupdate xmlns:d="DAV:">
+  <d:set>
+    <d:prop>
+      <d:displayname>{}</d:displayname>
+    </d:prop>
+  </d:set>
+</d:propertyupdate>"#,
+        quick_xml::escape::escape(title)
+    )
+}
+
+fn parse_multistatus(xml: &str) -> anyhow::Result<Vec<Response>> {
+    let mut reader = quick_xml::Reader::from_str(xml);
+    reader.trim_text(true);
+
+    let mut responses = Vec::new();
+    let mut current: Option<Response> = None;
+    let mut path: Vec<String> = Vec::new();
+
+    loop {
+        let event = reader.read_event()?;
+        let text = match &event {
+            Event::Start(e) | Event::Empty(e) => {
+                let name = String::from_utf8_lossy(e.local_name().as_ref()).to_string();
+                match name.as_str() {
+                    "response" => current = Some(Response::default()),
+                    "calendar" if path.last().map(|p| p.as_str()) == Some("resourcetype") => {
+                        if let Some(r) = current.as_mut() {
+                            r.is_calendar = true;
+                        }
+                    }
+                    "comp" => {
+                        let vtodo = e.attributes().flatten().any(|a| {
+                            a.key.local_name().as_ref() == b"name" && a.value.as_ref() == b"VTODO"
+                        });
+                        if let Some(r) = current.as_mut().filter(|_| vtodo) {
+                            r.supports_vtodo = true;
+                        }
+                    }
+                    _ => {}
+                }
+                if let Event::Start(_) = event {
+                    path.push(name);
+                }
+                None
+            }
+            Event::End(_) => {
+                if path.pop().as_deref() == Some("response") {
+                    responses.extend(current.take());
+                }
+                None
+            }
+            Event::Text(t) => Some(t.unescape()?.to_string()),
+            Event::CData(c) => Some(String::from_utf8_lossy(c.as_ref()).to_string()),
+            Event::Eof => break,
+            _ => None,
+        };
+
+        if let (Some(text), Some(r)) = (text, current.as_mut()) {
+            match path.last().map(|p| p.as_str()) {
+                Some("href") if path.len() >= 2 && path[path.len() - 2] == "response" => {
+                    r.href = text
+                }
+                Some("displayname") => r.displayname = Some(text),
+                Some("getetag") => r.etag = Some(text),
+                Some("calendar-data") => {
+                    r.calendar_data = Some(r.calendar_data.take().unwrap_or_default() + &text)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(responses)
+}
+
+/// Content lines with folded lines joined back together.
+fn unfold(ics: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in ics.lines() {
+        match line.strip_prefix([' ', '\t']) {
+            Some(rest) if !lines.is_empty() => {
+                if let Some(last) = lines.last_mut() {
+                    last.push_str(rest);
+                }
+            }
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    lines
+}
+
+/// Splits a content line into its name, parameters and value.
+fn split_line(line: &str) -> Option<(String, Vec<String>, String)> {
+    let (head, value) = line.split_once(':')?;
+    let mut parts = head.split(';');
+    let name = parts.next()?.to_ascii_uppercase();
+
+    Some((
+        name,
+        parts
+            .map(|p| match p.split_once('=') {
+                // Time zone ids are case sensitive.
+                Some((key, id)) if key.eq_ignore_ascii_case("TZID") => format!("TZID={}", id),
+                _ => p.to_ascii_uppercase(),
+            })
+            .collect(),
+        value.to_string(),
+    ))
+}
+
+fn unescape(value: &str) -> String {
+    let mut result = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') | Some('N') => result.push('\n'),
+                Some(c) => result.push(c),
+                None => {}
+            },
+            c => result.push(c),
+        }
+    }
+
+    result
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Folds a content line to 75 octets, as required by RFC 5545.
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+
+    folded
+}
+
+fn time_zone(params: &[String]) -> Option<Tz> {
+    params
+        .iter()
+        .find_map(|p| p.strip_prefix("TZID="))?
+        .trim_matches('"')
+        .parse()
+        .ok()
+}
+
+/// Due times in UTC or a time zone are shown in local time, floating ones as they are.
+fn parse_due(params: &[String], value: &str) -> Option<TimestampType> {
+    if params.iter().any(|p| p == "VALUE=DATE") || value.len() == 8 {
+        return NaiveDate::parse_from_str(value, "%Y%m%d")
+            .ok()
+            .map(TimestampType::Date);
+    }
+
+    let datetime =
+        NaiveDateTime::parse_from_str(value.trim_end_matches('Z'), "%Y%m%dT%H%M%S").ok()?;
+    let local = match (value.ends_with('Z'), time_zone(params)) {
+        (true, _) => Utc.from_utc_datetime(&datetime).with_timezone(&Local),
+        (false, Some(tz)) => tz
+            .from_local_datetime(&datetime)
+            .earliest()?
+            .with_timezone(&Local),
+        (false, None) => return Some(TimestampType::DateTime(datetime)),
+    };
+
+    Some(TimestampType::DateTime(local.naive_local()))
+}
+
+/// Writes a local due time the way the VTODO had its due time, in UTC, a time zone or floating.
+fn format_due(datetime: &NaiveDateTime, params: &[String], value: &str) -> String {
+    let local = Local.from_local_datetime(datetime).earliest();
+    match (value.ends_with('Z'), time_zone(params), local) {
+        (true, _, Some(local)) => {
+            format!("DUE:{}", local.with_timezone(&Utc).format("%Y%m%dT%H%M%SZ"))
+        }
+        (false, Some(tz), Some(local)) => format!(
+            "DUE;TZID={}:{}",
+            tz.name(),
+            local.with_timezone(&tz).format("%Y%m%dT%H%M%S")
+        ),
+        _ => format!("DUE:{}", datetime.format("%Y%m%dT%H%M%S")),
+    }
+}
+
+/// Sort orders are numbers, positions are compared as strings. Negative ones sort first, as
+/// `-` comes before the digits.
+fn format_position(order: i64) -> String {
+    match order {
+        0.. => format!("{:020}", order),
+        _ => format!("-{:020}", order.wrapping_sub(i64::MIN)),
+    }
+}
+
+fn parse_position(position: &str) -> Option<i64> {
+    match position.strip_prefix('-') {
+        Some(offset) => Some(offset.parse::<i64>().ok()?.wrapping_add(i64::MIN)),
+        None => position.parse().ok(),
+    }
+}
+
+fn ics_to_task(ics: &str, etag: Option<String>) -> Option<Task> {
+    let mut in_vtodo = false;
+    let mut task = Task::new("", Status::Todo, "", None, None);
+
+    for line in unfold(ics) {
+        match line.as_str() {
+            "BEGIN:VTODO" => in_vtodo = true,
+            "END:VTODO" => break,
+            _ if in_vtodo => {
+                let Some((name, params, value)) = split_line(&line) else {
+                    continue;
+                };
+                match name.as_str() {
+                    "UID" => task.id = value,
+                    "SUMMARY" => task.title = unescape(&value),
+                    "DESCRIPTION" => task.notes = Some(unescape(&value)),
+                    "STATUS" => {
+                        task.status = match value.as_str() {
+                            "NEEDS-ACTION" | "IN-PROCESS" => Status::Todo,
+                            "COMPLETED" => Status::Done,
+                            _ => Status::Unknown,
+                        }
+                    }
+                    "DUE" => task.due = parse_due(&params, &value),
+                    "RELATED-TO" if is_parent(&params) => task.parent = Some(value),
+                    "X-APPLE-SORT-ORDER" => {
+                        task.position = value.parse::<i64>().ok().map(format_position)
+                    }
+                    "LAST-MODIFIED" => task.updated = Some(value),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Some(Task { etag, ..task }).filter(|t| in_vtodo && !t.id.is_empty())
+}
+
+fn new_ics(uid: &str) -> String {
+    [
+        "BEGIN:VCALENDAR",
+        "VERSION:2.0",
+        "PRODID:-//tudo//EN",
+        "BEGIN:VTODO",
+        &format!("UID:{}", uid),
+        "END:VTODO",
+        "END:VCALENDAR",
+    ]
+    .join("\r\n")
+}
+
+/// Properties tudo writes, everything else in the VTODO is kept as it is. Of the RELATED-TO
+/// lines only the parent is written, see `is_parent`.
+const MANAGED: [&str; 8] = [
+    "SUMMARY",
+    "DESCRIPTION",
+    "STATUS",
+    "COMPLETED",
+    "DUE",
+    "X-APPLE-SORT-ORDER",
+    "LAST-MODIFIED",
+    "DTSTAMP",
+];
+
+/// Whether the parameters of a RELATED-TO line make it point at the parent, which is the default.
+fn is_parent(params: &[String]) -> bool {
+    !params
+        .iter()
+        .any(|p| p.starts_with("RELTYPE=") && p != "RELTYPE=PARENT")
+}
+
+/// Parameters and value of a property of the VTODO.
+fn property(ics: &str, name: &str) -> Option<(Vec<String>, String)> {
+    let mut in_vtodo = false;
+    for line in unfold(ics) {
+        match line.as_str() {
+            "BEGIN:VTODO" => in_vtodo = true,
+            "END:VTODO" => break,
+            _ if in_vtodo => match split_line(&line) {
+                Some((n, params, value)) if n == name => return Some((params, value)),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    None
+}
+
+fn update_ics(ics: &str, task: &Task) -> anyhow::Result<String> {
+    let now = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let (due_params, due) = property(ics, "DUE").unwrap_or_default();
+    // Saving a completed task again keeps the time it was completed at.
+    let completed = property(ics, "STATUS")
+        .filter(|(_, status)| status == "COMPLETED")
+        .and_then(|_| property(ics, "COMPLETED"))
+        .map_or(now.clone(), |(_, completed)| completed);
+
+    let mut properties = vec![
+        format!("SUMMARY:{}", escape(&task.title)),
+        format!("DTSTAMP:{}", now),
+        format!("LAST-MODIFIED:{}", now),
+    ];
+    if let Some(notes) = task.notes.as_deref().filter(|n| !n.is_empty()) {
+        properties.push(format!("DESCRIPTION:{}", escape(notes)));
+    }
+    match task.status {
+        Status::Todo => properties.push("STATUS:NEEDS-ACTION".to_string()),
+        Status::Done => {
+            properties.push("STATUS:COMPLETED".to_string());
+            properties.push(format!("COMPLETED:{}", completed));
+        }
+        Status::Unknown => {}
+    }
+    match &task.due {
+        Some(TimestampType::Date(date)) => {
+            properties.push(format!("DUE;VALUE=DATE:{}", date.format("%Y%m%d")))
+        }
+        Some(TimestampType::DateTime(datetime)) => {
+            properties.push(format_due(datetime, &due_params, &due))
+        }
+        Some(TimestampType::Time(_)) => anyhow::bail!("caldav due dates need a date"),
+        None => {}
+    }
+    if let Some(parent) = &task.parent {
+        properties.push(format!("RELATED-TO;RELTYPE=PARENT:{}", parent));
+    }
+    if let Some(order) = task.position.as_deref().and_then(parse_position) {
+        properties.push(format!("X-APPLE-SORT-ORDER:{}", order));
+    }
+
+    let mut lines = Vec::new();
+    let mut in_vtodo = false;
+    for line in unfold(ics) {
+        match line.as_str() {
+            "BEGIN:VTODO" => in_vtodo = true,
+            "END:VTODO" => {
+                in_vtodo = false;
+                lines.append(&mut properties);
+            }
+            _ if in_vtodo => {
+                let managed = split_line(&line).is_some_and(|(name, params, _)| {
+                    MANAGED.contains(&name.as_str()) || (name == "RELATED-TO" && is_parent(&params))
+                });
+                if managed {
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        lines.push(line);
+    }
+
+    Ok(lines
+        .iter()
+        .map(|l| fold(l))
+        .collect::<Vec<_>>()
+        .join("\r\n")
+        + "\r\n")
+}
+
+fn new_uid() -> String {
+    format!("tudo-{:x}", chrono::Utc::now().timestamp_nanos())
+}
+
+pub async fn setup(account: &AccountCfg) -> anyhow::Result<impl Provider> {
+    let mut provider = CachedProvider::new(
+        CaldavProvider::new(&account.caldav)?,
+        account.path("cache", "caldav.json"),
+    )?;
+    if provider.is_empty() {
+        provider.sync().await?;
+    }
+
+    Ok(provider)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ICS: &str = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+BEGIN:VTODO\r
+UID:a\r
+SUMMARY:Write report\\, draft\r
+DESCRIPTION:First line\\nsecond line\r
+STATUS:COMPLETED\r
+COMPLETED:20240101T100000Z\r
+DUE;TZID=Europe/Berlin:20240102T090000\r
+RELATED-TO;RELTYPE=PARENT:p\r
+X-APPLE-SORT-ORDER:-5\r
+CATEGORIES:work\r
+END:VTODO\r
+END:VCALENDAR\r
+";
+
+    fn local(datetime: chrono::DateTime<Tz>) -> NaiveDateTime {
+        datetime.with_timezone(&Local).naive_local()
+    }
+
+    #[test]
+    fn parses_multistatus() {
+        let xml = r#"<?xml version="1.0"?>
+<d:multistatus xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">
+  <d:response>
+    <d:href>/user/tasks/</d:href>
+    <d:propstat><d:prop>
+      <d:resourcetype><d:collection/><c:calendar/></d:resourcetype>
+      <d:displayname>Tasks &amp; more</d:displayname>
+      <c:supported-calendar-component-set><c:comp name="VTODO"/></c:supported-calendar-component-set>
+    </d:prop></d:propstat>
+  </d:response>
+  <d:response>
+    <d:href>/user/tasks/a.ics</d:href>
+    <d:propstat><d:prop>
+      <d:getetag>"1"</d:getetag>
+      <c:calendar-data>BEGIN:VCALENDAR</c:calendar-data>
+    </d:prop></d:propstat>
+  </d:response>
+</d:multistatus>"#;
+
+        let responses = parse_multistatus(xml).unwrap();
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0].href, "/user/tasks/");
+        assert_eq!(responses[0].displayname.as_deref(), Some("Tasks & more"));
+        assert!(responses[0].is_calendar && responses[0].supports_vtodo);
+        assert_eq!(responses[1].href, "/user/tasks/a.ics");
+        assert_eq!(responses[1].etag.as_deref(), Some("\"1\""));
+        assert_eq!(
+            responses[1].calendar_data.as_deref(),
+            Some("BEGIN:VCALENDAR")
+        );
+        assert!(!responses[1].is_calendar);
+    }
+
+    #[test]
+    fn folds_and_unfolds_long_lines() {
+        let line = format!("SUMMARY:{}", "ä".repeat(60));
+        let folded = fold(&line);
+
+        assert!(folded.split("\r\n").all(|l| l.len() <= 75));
+        assert_eq!(unfold(&folded), [line]);
+        assert_eq!(unfold("A:1\r\nB:2\r\n\t3"), ["A:1", "B:23"]);
+    }
+
+    #[test]
+    fn reads_a_task() {
+        let task = ics_to_task(ICS, Some("\"1\"".to_string())).unwrap();
+
+        assert_eq!(task.id, "a");
+        assert_eq!(task.title, "Write report, draft");
+        assert_eq!(task.notes.as_deref(), Some("First line\nsecond line"));
+        assert!(matches!(task.status, Status::Done));
+        assert_eq!(task.parent.as_deref(), Some("p"));
+        assert_eq!(task.etag.as_deref(), Some("\"1\""));
+        assert_eq!(
+            task.due,
+            Some(TimestampType::DateTime(local(
+                chrono_tz::Europe::Berlin
+                    .with_ymd_and_hms(2024, 1, 2, 9, 0, 0)
+                    .unwrap()
+            )))
+        );
+    }
+
+    #[test]
+    fn reads_due_times_in_utc_and_dates() {
+        let due = |line: &str| {
+            let ics = ICS.replace("DUE;TZID=Europe/Berlin:20240102T090000", line);
+            ics_to_task(&ics, None).unwrap().due
+        };
+
+        assert_eq!(
+            due("DUE:20240102T090000Z"),
+            Some(TimestampType::DateTime(local(
+                chrono_tz::UTC
+                    .with_ymd_and_hms(2024, 1, 2, 9, 0, 0)
+                    .unwrap()
+            )))
+        );
+        assert_eq!(
+            due("DUE:20240102T090000"),
+            Some(TimestampType::DateTime(
+                NaiveDate::from_ymd_opt(2024, 1, 2)
+                    .unwrap()
+                    .and_hms_opt(9, 0, 0)
+                    .unwrap()
+            ))
+        );
+        assert_eq!(
+            due("DUE;VALUE=DATE:20240102"),
+            Some(TimestampType::Date(
+                NaiveDate::from_ymd_opt(2024, 1, 2).unwrap()
+            ))
+        );
+    }
+
+    #[test]
+    fn keeps_negative_sort_orders_in_order() {
+        let orders = [-5, -1, 0, 3, 12];
+        let positions = orders.map(format_position);
+
+        assert!(positions.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(positions.map(|p| parse_position(&p).unwrap()), orders);
+        assert_eq!(
+            ics_to_task(ICS, None).unwrap().position,
+            Some(format_position(-5))
+        );
+    }
+
+    #[test]
+    fn update_keeps_what_tudo_does_not_change() {
+        let task = ics_to_task(ICS, None).unwrap();
+        let ics = update_ics(ICS, &task).unwrap();
+
+        assert!(ics.contains("CATEGORIES:work\r\n"));
+        assert!(ics.contains("COMPLETED:20240101T100000Z\r\n"));
+        assert!(ics.contains("DUE;TZID=Europe/Berlin:20240102T090000\r\n"));
+        assert!(ics.contains("X-APPLE-SORT-ORDER:-5\r\n"));
+        assert!(ics.contains("SUMMARY:Write report\\, draft\r\n"));
+        assert!(ics.ends_with("END:VTODO\r\nEND:VCALENDAR\r\n"));
+
+        let reread = ics_to_task(&ics, None).unwrap();
+        assert_eq!(reread.title, task.title);
+        assert_eq!(reread.due, task.due);
+    }
+
+    #[test]
+    fn update_completes_and_reopens() {
+        let open = ICS
+            .replace("STATUS:COMPLETED", "STATUS:NEEDS-ACTION")
+            .replace("COMPLETED:20240101T100000Z\r\n", "");
+        let task = ics_to_task(&open, None).unwrap();
+
+        let done = update_ics(
+            &open,
+            &Task {
+                status: Status::Done,
+                ..task.clone()
+            },
+        )
+        .unwrap();
+        assert!(done.contains("STATUS:COMPLETED\r\n"));
+        assert!(!done.contains("COMPLETED:20240101T100000Z"));
+        assert!(done.contains("\r\nCOMPLETED:"));
+
+        let reopened = update_ics(ICS, &task).unwrap();
+        assert!(reopened.contains("STATUS:NEEDS-ACTION\r\n"));
+        assert!(!reopened.contains("\r\nCOMPLETED:"));
+    }
+
+    #[test]
+    fn update_keeps_relations_other_than_the_parent() {
+        let ics = ICS.replace(
+            "RELATED-TO;RELTYPE=PARENT:p\r\n",
+            "RELATED-TO;RELTYPE=SIBLING:s\r\nRELATED-TO;RELTYPE=PARENT:p\r\n",
+        );
+        let task = ics_to_task(&ics, None).unwrap();
+        assert_eq!(task.parent.as_deref(), Some("p"));
+
+        let moved = update_ics(
+            &ics,
+            &Task {
+                parent: Some("q".to_string()),
+                ..task
+            },
+        )
+        .unwrap();
+        assert!(moved.contains("RELATED-TO;RELTYPE=SIBLING:s\r\n"));
+        assert!(moved.contains("RELATED-TO;RELTYPE=PARENT:q\r\n"));
+        assert!(!moved.contains(":p\r\n"));
+        assert_eq!(
+            ics_to_task(&moved, None).unwrap().parent.as_deref(),
+            Some("q")
+        );
+    }
+}
@@ -1,18 +1,20 @@
 extern crate google_tasks1 as tasks1;
 
-use envpath::EnvPath;
-use hyper::client::HttpConnector;
+use std::path::PathBuf;
+
+use hyper::{client::HttpConnector, Body, Method, Request, StatusCode};
 use hyper_rustls::HttpsConnector;
 use tasks1::{
-    oauth2::{read_application_secret, ApplicationSecret, InstalledFlowReturnMethod},
+    oauth2::{authenticator::Authenticator, read_application_secret, ApplicationSecret},
     TasksHub,
 };
-use yup_oauth2::{authenticator::Authenticator, InstalledFlowAuthenticator};
 
 use crate::{
-    app::{App, Status, Task, Tasklist},
-    config::Cfg,
-    provider::Provider,
+    app::{remove_subtree, Status, Task, Tasklist},
+    cache::CachedProvider,
+    config::AccountCfg,
+    oauth,
+    provider::{Conflict, Loader, Provider},
     timestamps::TimestampType,
 };
 
@@ -36,6 +38,55 @@ impl GoogleTasksProvider {
         Ok(())
     }
 
+    /// Patches the fields tudo edits, the others (like the completion time) are kept. The
+    /// generated client can not send `If-Match`, so the request is made here.
+    async fn patch_task(
+        &self,
+        tasklist_id: &str,
+        task: &Task,
+    ) -> anyhow::Result<(StatusCode, String)> {
+        let gtask = task_to_gtask(task);
+        let mut body = serde_json::json!({
+            "id": gtask.id,
+            "title": gtask.title,
+            "due": gtask.due,
+            "notes": gtask.notes,
+        });
+        if let Some(status) = gtask.status {
+            body["status"] = status.into();
+        }
+
+        let token = self
+            .hub
+            .auth
+            .get_token(&[tasks1::api::Scope::Full.as_ref()])
+            .await
+            .map_err(|err| anyhow::anyhow!(err))?;
+        let mut request = Request::builder()
+            .method(Method::PATCH)
+            .uri(format!(
+                "https://tasks.googleapis.com/tasks/v1/lists/{}/tasks/{}",
+                tasklist_id, task.id
+            ))
+            .header("Content-Type", "application/json");
+        if let Some(token) = token {
+            request = request.header("Authorization", format!("Bearer {}", token));
+        }
+        if let Some(etag) = &task.etag {
+            request = request.header("If-Match", etag);
+        }
+
+        let response = self
+            .hub
+            .client
+            .request(request.body(Body::from(body.to_string()))?)
+            .await?;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+
+        Ok((status, String::from_utf8_lossy(&body).to_string()))
+    }
+
     async fn load_tasklist(&mut self, id: &str) -> anyhow::Result<()> {
         let tasks = load_tasks(&self.hub, id).await?;
         let tasklist = self
@@ -48,10 +99,9 @@ impl GoogleTasksProvider {
             ..tasklist.clone()
         };
 
-        self.tasklists
-            .iter_mut()
-            .find(|t| t.id == id)
-            .map(|t| *t = tasklist);
+        if let Some(t) = self.tasklists.iter_mut().find(|t| t.id == id) {
+            *t = tasklist;
+        }
 
         Ok(())
     }
@@ -63,68 +113,185 @@ impl Provider for GoogleTasksProvider {
         &self.tasklists
     }
 
-    async fn update_task(&mut self, tasklist_id: &str, task: &Task) -> anyhow::Result<()> {
+    async fn sync(&mut self) -> anyhow::Result<()> {
+        self.load_tasklists().await
+    }
+
+    fn loader(&self) -> Option<Loader> {
+        let hub = self.hub.clone();
+
+        Some(Box::new(move || {
+            let hub = hub.clone();
+            Box::pin(async move { load_tasklists(&hub).await })
+        }))
+    }
+
+    fn set_tasklists(&mut self, tasklists: Vec<Tasklist>) {
+        self.tasklists = tasklists;
+    }
+
+    fn is_offline_error(&self, err: &anyhow::Error) -> bool {
+        matches!(
+            err.downcast_ref::<tasks1::Error>(),
+            Some(
+                tasks1::Error::HttpError(_) | tasks1::Error::Io(_) | tasks1::Error::MissingToken(_)
+            )
+        )
+    }
+
+    async fn create_tasklist(&mut self, title: &str) -> anyhow::Result<String> {
+        let gtasklist = tasks1::api::TaskList {
+            title: Some(title.to_string()),
+            ..Default::default()
+        };
+
+        let (_, gtasklist) = self.hub.tasklists().insert(gtasklist).doit().await?;
+        let id = gtasklist
+            .id
+            .ok_or(anyhow::anyhow!("created tasklist has no id"))?;
+
+        self.tasklists
+            .push(Tasklist::new(id.clone(), title.to_string(), &Vec::new()));
+
+        Ok(id)
+    }
+
+    async fn rename_tasklist(&mut self, tasklist_id: &str, title: &str) -> anyhow::Result<()> {
+        let gtasklist = tasks1::api::TaskList {
+            title: Some(title.to_string()),
+            ..Default::default()
+        };
+
         self.hub
-            .tasks()
-            .update(task_to_gtask(&task), tasklist_id, &task.id)
+            .tasklists()
+            .patch(gtasklist, tasklist_id)
             .doit()
             .await?;
 
+        if let Some(t) = self.tasklists.iter_mut().find(|t| t.id == tasklist_id) {
+            t.title = title.to_string();
+        }
+
+        Ok(())
+    }
+
+    async fn delete_tasklist(&mut self, tasklist_id: &str) -> anyhow::Result<()> {
+        self.hub.tasklists().delete(tasklist_id).doit().await?;
+
+        self.tasklists.retain(|t| t.id != tasklist_id);
+
+        Ok(())
+    }
+
+    async fn update_task(&mut self, tasklist_id: &str, task: &Task) -> anyhow::Result<()> {
+        let (status, body) = self.patch_task(tasklist_id, task).await?;
+        if status == StatusCode::PRECONDITION_FAILED {
+            let (_, remote) = self.hub.tasks().get(tasklist_id, &task.id).doit().await?;
+            let remote = gtask_to_task(&remote)
+                .ok_or(anyhow::anyhow!("task {} can not be parsed", task.id))?;
+
+            return Err(Conflict {
+                tasklist_id: tasklist_id.to_string(),
+                local: task.clone(),
+                remote,
+            }
+            .into());
+        }
+        if !status.is_success() {
+            anyhow::bail!("updating task {} failed with {}: {}", task.id, status, body);
+        }
+
+        let updated = serde_json::from_str::<tasks1::api::Task>(&body)?;
+        if let Some(t) = self
+            .tasklists
+            .iter_mut()
+            .find(|t| t.id == tasklist_id)
+            .and_then(|t| t.tasks.iter_mut().find(|t| t.id == task.id))
+        {
+            *t = gtask_to_task(&updated)
+                .ok_or(anyhow::anyhow!("task {} can not be parsed", task.id))?;
+        }
+
+        Ok(())
+    }
+
+    async fn create_task(&mut self, tasklist_id: &str, task: &Task) -> anyhow::Result<String> {
+        let gtask = tasks1::api::Task {
+            id: None,
+            ..task_to_gtask(task)
+        };
+
+        let mut call = self.hub.tasks().insert(gtask, tasklist_id);
+        if let Some(parent) = &task.parent {
+            call = call.parent(parent);
+        }
+        let (_, gtask) = call.doit().await?;
+        let created = gtask_to_task(&gtask).ok_or(anyhow::anyhow!("created task has no id"))?;
+
+        // The response has the id, position and etag, so the tasklist is not loaded again.
+        let id = created.id.clone();
+        if let Some(t) = self.tasklists.iter_mut().find(|t| t.id == tasklist_id) {
+            t.tasks.push(created);
+        }
+
+        Ok(id)
+    }
+
+    async fn delete_task(&mut self, tasklist_id: &str, task_id: &str) -> anyhow::Result<()> {
+        self.hub.tasks().delete(tasklist_id, task_id).doit().await?;
+
+        if let Some(t) = self.tasklists.iter_mut().find(|t| t.id == tasklist_id) {
+            remove_subtree(&mut t.tasks, task_id);
+        }
+
+        Ok(())
+    }
+
+    async fn move_task(
+        &mut self,
+        tasklist_id: &str,
+        task_id: &str,
+        parent: Option<&str>,
+        previous: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let mut call = self.hub.tasks().move_(tasklist_id, task_id);
+        if let Some(parent) = parent {
+            call = call.parent(parent);
+        }
+        if let Some(previous) = previous {
+            call = call.previous(previous);
+        }
+        call.doit().await?;
+
         self.load_tasklist(tasklist_id).await?;
 
         Ok(())
     }
 }
 
-pub async fn setup(cfg: &Cfg) -> anyhow::Result<App> {
-    let auth_data = login(&cfg.client_secret).await?;
+pub async fn setup(account: &AccountCfg) -> anyhow::Result<impl Provider> {
+    let auth_data = login(&account.client_secret, account.path("cache", "config.toml")).await?;
     let hub = get_hub(auth_data).await;
 
-    let mut provider = GoogleTasksProvider::new(hub);
-    provider.load_tasklists().await?;
-
-    let app = App::new(provider);
+    let mut provider = CachedProvider::new(
+        GoogleTasksProvider::new(hub),
+        account.path("cache", "google.json"),
+    )?;
+    // Nothing to show yet, so the first sync (and login) happens before the ui starts.
+    if provider.is_empty() {
+        provider.sync().await?;
+    }
 
-    Ok(app)
+    Ok(provider)
 }
 
 async fn login(
     client_secret: &str,
+    token_cache: PathBuf,
 ) -> anyhow::Result<Authenticator<HttpsConnector<HttpConnector>>> {
     let secret: ApplicationSecret = read_application_secret(client_secret).await?;
 
-    let token_cache = EnvPath::from(["$dir: cache", "tudo", "config.toml"])
-        .de()
-        .to_path_buf();
-
-    let token_cache = if token_cache.exists() {
-        Some(token_cache)
-    } else {
-        token_cache
-            .parent()
-            .map(|prefix| {
-                std::fs::create_dir_all(prefix)
-                    .ok()
-                    .map(|_| token_cache.clone())
-            })
-            .flatten()
-    };
-
-    let auth = match token_cache {
-        Some(token_cache) => {
-            InstalledFlowAuthenticator::builder(secret, InstalledFlowReturnMethod::HTTPRedirect)
-                .persist_tokens_to_disk(token_cache)
-                .build()
-                .await?
-        }
-        None => {
-            InstalledFlowAuthenticator::builder(secret, InstalledFlowReturnMethod::HTTPRedirect)
-                .build()
-                .await?
-        }
-    };
-
-    Ok(auth)
+    oauth::login(secret, token_cache).await
 }
 
 async fn get_hub(
@@ -146,11 +313,14 @@ async fn get_hub(
 async fn load_tasklists(
     hub: &TasksHub<HttpsConnector<HttpConnector>>,
 ) -> anyhow::Result<Vec<Tasklist>> {
-    let raw_tasklists = hub.tasklists().list().doit().await?.1.items;
-    let raw_tasklists = match raw_tasklists {
-        Some(raw_tasklists) => raw_tasklists,
-        _ => Vec::new(),
-    };
+    let raw_tasklists = hub
+        .tasklists()
+        .list()
+        .doit()
+        .await?
+        .1
+        .items
+        .unwrap_or_default();
 
     let mut tasklists = Vec::new();
     for tasklist in raw_tasklists.iter() {
@@ -163,7 +333,7 @@ async fn load_tasklists(
             tasklists.push(Tasklist::new(
                 id.to_owned(),
                 title.to_owned(),
-                &load_tasks(hub, id).await.unwrap_or(Vec::new()),
+                &load_tasks(hub, id).await.unwrap_or_default(),
             ));
         };
     }
@@ -176,16 +346,9 @@ async fn load_tasks(
     id: &str,
 ) -> anyhow::Result<Vec<Task>> {
     let raw_tasks = hub.tasks().list(id).doit().await?.1.items;
-    let raw_tasks = match raw_tasks {
-        Some(raw_tasks) => raw_tasks,
-        _ => Vec::new(),
-    };
+    let raw_tasks = raw_tasks.unwrap_or_default();
 
-    let tasks: Vec<Task> = raw_tasks
-        .iter()
-        .map(|gtask| gtask_to_task(gtask))
-        .flatten()
-        .collect();
+    let tasks: Vec<Task> = raw_tasks.iter().filter_map(gtask_to_task).collect();
 
     Ok(tasks)
 }
@@ -197,10 +360,14 @@ fn gtask_to_task(gtask: &tasks1::api::Task) -> Option<Task> {
         title: Some(title),
         due,
         notes,
+        parent,
+        position,
+        etag,
+        updated,
         ..
     } = gtask
     {
-        Some(Task::new(
+        let task = Task::new(
             &id.clone(),
             match status.as_deref() {
                 Some("needsAction") => Status::Todo,
@@ -208,28 +375,36 @@ fn gtask_to_task(gtask: &tasks1::api::Task) -> Option<Task> {
                 _ => Status::Unknown,
             },
             &title.clone(),
-            due.clone()
-                .map(|x| {
-                    chrono::DateTime::parse_from_rfc3339(&x)
-                        .ok()
-                        .map(|y| match x {
-                            i if i.ends_with("T00:00:00.000Z") => {
-                                TimestampType::Date(y.naive_local().date())
-                            }
-                            i if i.starts_with("0000-00-00T") => {
-                                TimestampType::Time(y.naive_local().time())
-                            }
-                            _ => TimestampType::DateTime(y.naive_local()),
-                        })
-                })
-                .flatten(),
+            due.clone().and_then(|x| {
+                chrono::DateTime::parse_from_rfc3339(&x)
+                    .ok()
+                    .map(|y| match x {
+                        i if i.ends_with("T00:00:00.000Z") => {
+                            TimestampType::Date(y.naive_local().date())
+                        }
+                        i if i.starts_with("0000-00-00T") => {
+                            TimestampType::Time(y.naive_local().time())
+                        }
+                        _ => TimestampType::DateTime(y.naive_local()),
+                    })
+            }),
             notes.as_deref(),
-        ))
+        );
+
+        Some(Task {
+            parent: parent.clone(),
+            position: position.clone(),
+            etag: etag.clone(),
+            updated: updated.clone(),
+            ..task
+        })
     } else {
         None
     }
 }
 
+/// Converts the fields tudo edits (id, title, status, due and notes) into a Google task, the
+/// others are left unset.
 fn task_to_gtask(task: &Task) -> tasks1::api::Task {
     let status = match task.status {
         Status::Todo => Some(String::from("needsAction")),
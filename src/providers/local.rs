@@ -0,0 +1,156 @@
+use std::path::PathBuf;
+
+use crate::{
+    app::{place, remove_subtree, Task, Tasklist},
+    config::AccountCfg,
+    provider::Provider,
+};
+
+/// Keeps tasklists in a json file, for use without an account.
+pub struct LocalProvider {
+    path: PathBuf,
+
+    tasklists: Vec<Tasklist>,
+}
+
+impl LocalProvider {
+    fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            tasklists: Vec::new(),
+        }
+    }
+
+    fn load(&mut self) -> anyhow::Result<()> {
+        self.tasklists = match std::fs::read_to_string(&self.path) {
+            Ok(content) => serde_json::from_str(&content)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(())
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string_pretty(&self.tasklists)?)?;
+
+        Ok(())
+    }
+
+    fn tasks_mut(&mut self, tasklist_id: &str) -> anyhow::Result<&mut Vec<Task>> {
+        self.tasklists
+            .iter_mut()
+            .find(|t| t.id == tasklist_id)
+            .map(|t| &mut t.tasks)
+            .ok_or(anyhow::anyhow!(
+                "tasklist with id {} not found",
+                tasklist_id
+            ))
+    }
+}
+
+#[async_trait::async_trait]
+impl Provider for LocalProvider {
+    fn get_tasklists(&self) -> &Vec<Tasklist> {
+        &self.tasklists
+    }
+
+    async fn sync(&mut self) -> anyhow::Result<()> {
+        self.load()
+    }
+
+    async fn create_tasklist(&mut self, title: &str) -> anyhow::Result<String> {
+        let id = new_id();
+        self.tasklists
+            .push(Tasklist::new(id.clone(), title.to_string(), &[]));
+        self.save()?;
+
+        Ok(id)
+    }
+
+    async fn rename_tasklist(&mut self, tasklist_id: &str, title: &str) -> anyhow::Result<()> {
+        let tasklist = self
+            .tasklists
+            .iter_mut()
+            .find(|t| t.id == tasklist_id)
+            .ok_or(anyhow::anyhow!(
+                "tasklist with id {} not found",
+                tasklist_id
+            ))?;
+        tasklist.title = title.to_string();
+
+        self.save()
+    }
+
+    async fn delete_tasklist(&mut self, tasklist_id: &str) -> anyhow::Result<()> {
+        self.tasklists.retain(|t| t.id != tasklist_id);
+
+        self.save()
+    }
+
+    async fn update_task(&mut self, tasklist_id: &str, task: &Task) -> anyhow::Result<()> {
+        let tasks = self.tasks_mut(tasklist_id)?;
+        let t = tasks
+            .iter_mut()
+            .find(|t| t.id == task.id)
+            .ok_or(anyhow::anyhow!("task with id {} not found", task.id))?;
+        *t = Task {
+            parent: t.parent.clone(),
+            position: t.position.clone(),
+            etag: None,
+            updated: Some(chrono::Utc::now().to_rfc3339()),
+            ..task.clone()
+        };
+
+        self.save()
+    }
+
+    async fn create_task(&mut self, tasklist_id: &str, task: &Task) -> anyhow::Result<String> {
+        let id = new_id();
+        let tasks = self.tasks_mut(tasklist_id)?;
+        tasks.push(Task {
+            id: id.clone(),
+            etag: None,
+            updated: Some(chrono::Utc::now().to_rfc3339()),
+            ..task.clone()
+        });
+        place(tasks, &id, task.parent.as_deref(), None);
+
+        self.save()?;
+
+        Ok(id)
+    }
+
+    async fn delete_task(&mut self, tasklist_id: &str, task_id: &str) -> anyhow::Result<()> {
+        remove_subtree(self.tasks_mut(tasklist_id)?, task_id);
+
+        self.save()
+    }
+
+    async fn move_task(
+        &mut self,
+        tasklist_id: &str,
+        task_id: &str,
+        parent: Option<&str>,
+        previous: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let tasks = self.tasks_mut(tasklist_id)?;
+        place(tasks, task_id, parent, previous);
+
+        self.save()
+    }
+}
+
+fn new_id() -> String {
+    format!("{:x}", chrono::Utc::now().timestamp_nanos())
+}
+
+pub fn setup(account: &AccountCfg) -> anyhow::Result<LocalProvider> {
+    let mut provider = LocalProvider::new(account.path("cfg", "tasks.json"));
+    provider.load()?;
+
+    Ok(provider)
+}
@@ -0,0 +1,760 @@
+use std::path::PathBuf;
+
+use chrono::{Local, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use hyper::{client::HttpConnector, Body, Method, Request, StatusCode};
+use hyper_rustls::HttpsConnector;
+use serde::{Deserialize, Serialize};
+use yup_oauth2::{authenticator::Authenticator, ApplicationSecret};
+
+use crate::{
+    app::{Status, Task, Tasklist},
+    cache::CachedProvider,
+    config::{AccountCfg, MicrosoftCfg},
+    oauth,
+    provider::{Conflict, Loader, Provider},
+    timestamps::TimestampType,
+};
+
+const SCOPES: [&str; 2] = ["Tasks.ReadWrite", "offline_access"];
+
+#[derive(Deserialize)]
+struct Page<T> {
+    value: Vec<T>,
+    #[serde(rename = "@odata.nextLink")]
+    next_link: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphList {
+    #[serde(skip_serializing)]
+    id: String,
+    display_name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphTask {
+    #[serde(skip_serializing)]
+    id: String,
+    title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<String>,
+    body: Option<GraphBody>,
+    due_date_time: Option<GraphDateTime>,
+    #[serde(skip_serializing)]
+    last_modified_date_time: Option<String>,
+    #[serde(rename = "@odata.etag", skip_serializing)]
+    etag: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphBody {
+    content: String,
+    content_type: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphDateTime {
+    date_time: String,
+    time_zone: String,
+}
+
+/// Http layer of the Graph api, requests are made relative to `base_url`.
+#[derive(Clone)]
+struct Graph {
+    client: hyper::Client<HttpsConnector<HttpConnector>>,
+    base_url: String,
+    /// Missing when no client id is configured, e.g. against a mock server.
+    auth: Option<Authenticator<HttpsConnector<HttpConnector>>>,
+}
+
+impl Graph {
+    fn new(base_url: &str, auth: Option<Authenticator<HttpsConnector<HttpConnector>>>) -> Self {
+        let client = hyper::Client::builder().build(
+            hyper_rustls::HttpsConnectorBuilder::new()
+                .with_native_roots()
+                .https_or_http()
+                .enable_http1()
+                .enable_http2()
+                .build(),
+        );
+
+        Self {
+            client,
+            base_url: base_url.to_string(),
+            auth,
+        }
+    }
+
+    async fn request(
+        &self,
+        method: Method,
+        url: &str,
+        etag: Option<&str>,
+        body: Option<String>,
+    ) -> anyhow::Result<(StatusCode, String)> {
+        let url = match url.starts_with("http") {
+            true => url.to_string(),
+            false => format!("{}{}", self.base_url.trim_end_matches('/'), url),
+        };
+
+        let mut request = Request::builder().method(method).uri(url);
+        if let Some(auth) = &self.auth {
+            let token = auth.token(&SCOPES).await?;
+            let token = token
+                .token()
+                .ok_or(anyhow::anyhow!("no microsoft access token"))?;
+            request = request.header("Authorization", format!("Bearer {}", token));
+        }
+        if let Some(etag) = etag {
+            request = request.header("If-Match", etag);
+        }
+        let request = match body {
+            Some(body) => request
+                .header("Content-Type", "application/json")
+                .body(Body::from(body))?,
+            None => request.body(Body::empty())?,
+        };
+
+        let response = self.client.request(request).await?;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+
+        Ok((status, String::from_utf8_lossy(&body).to_string()))
+    }
+
+    async fn send<T: Serialize + Sync>(
+        &self,
+        method: Method,
+        url: &str,
+        body: Option<&T>,
+    ) -> anyhow::Result<String> {
+        let body = body.map(serde_json::to_string).transpose()?;
+        let (status, response) = self.request(method.clone(), url, None, body).await?;
+        check(&method, url, status, &response)?;
+
+        Ok(response)
+    }
+
+    async fn list<T: for<'de> Deserialize<'de>>(&self, url: &str) -> anyhow::Result<Vec<T>> {
+        let mut items = Vec::new();
+        let mut next = Some(url.to_string());
+        while let Some(url) = next {
+            let page: Page<T> =
+                serde_json::from_str(&self.send::<()>(Method::GET, &url, None).await?)?;
+            items.extend(page.value);
+            next = page.next_link;
+        }
+
+        Ok(items)
+    }
+
+    async fn load_tasklists(&self) -> anyhow::Result<Vec<Tasklist>> {
+        let mut tasklists = Vec::new();
+        for list in self.list::<GraphList>("/me/todo/lists").await? {
+            let tasks = self.load_tasks(&list.id).await?;
+            tasklists.push(Tasklist::new(list.id, list.display_name, &tasks));
+        }
+
+        Ok(tasklists)
+    }
+
+    async fn load_tasks(&self, tasklist_id: &str) -> anyhow::Result<Vec<Task>> {
+        Ok(self
+            .list::<GraphTask>(&format!("/me/todo/lists/{}/tasks", tasklist_id))
+            .await?
+            .into_iter()
+            .map(graph_to_task)
+            .collect())
+    }
+}
+
+/// Turns an unsuccessful response into an error with the message Graph sent along.
+fn check(method: &Method, url: &str, status: StatusCode, response: &str) -> anyhow::Result<()> {
+    if status.is_success() {
+        return Ok(());
+    }
+
+    let message = serde_json::from_str::<serde_json::Value>(response)
+        .ok()
+        .and_then(|v| v["error"]["message"].as_str().map(|m| m.to_string()))
+        .unwrap_or_default();
+    anyhow::bail!("{} {} failed with {}: {}", method, url, status, message)
+}
+
+pub struct MicrosoftTodoProvider {
+    graph: Graph,
+
+    tasklists: Vec<Tasklist>,
+}
+
+impl MicrosoftTodoProvider {
+    fn new(graph: Graph) -> Self {
+        Self {
+            graph,
+            tasklists: Vec::new(),
+        }
+    }
+
+    async fn load_tasklist(&mut self, id: &str) -> anyhow::Result<()> {
+        let tasks = self.graph.load_tasks(id).await?;
+
+        if let Some(t) = self.tasklists.iter_mut().find(|t| t.id == id) {
+            t.tasks = tasks;
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Provider for MicrosoftTodoProvider {
+    fn get_tasklists(&self) -> &Vec<Tasklist> {
+        &self.tasklists
+    }
+
+    async fn sync(&mut self) -> anyhow::Result<()> {
+        self.tasklists = self.graph.load_tasklists().await?;
+
+        Ok(())
+    }
+
+    fn loader(&self) -> Option<Loader> {
+        let graph = self.graph.clone();
+
+        Some(Box::new(move || {
+            let graph = graph.clone();
+            Box::pin(async move { graph.load_tasklists().await })
+        }))
+    }
+
+    fn set_tasklists(&mut self, tasklists: Vec<Tasklist>) {
+        self.tasklists = tasklists;
+    }
+
+    fn is_offline_error(&self, err: &anyhow::Error) -> bool {
+        err.downcast_ref::<hyper::Error>()
+            .is_some_and(|e| e.is_connect() || e.is_timeout())
+    }
+
+    async fn create_tasklist(&mut self, title: &str) -> anyhow::Result<String> {
+        let list = GraphList {
+            id: String::new(),
+            display_name: title.to_string(),
+        };
+
+        let response = self
+            .graph
+            .send(Method::POST, "/me/todo/lists", Some(&list))
+            .await?;
+        let id = serde_json::from_str::<GraphList>(&response)?.id;
+
+        self.tasklists
+            .push(Tasklist::new(id.clone(), title.to_string(), &[]));
+
+        Ok(id)
+    }
+
+    async fn rename_tasklist(&mut self, tasklist_id: &str, title: &str) -> anyhow::Result<()> {
+        let list = GraphList {
+            id: String::new(),
+            display_name: title.to_string(),
+        };
+
+        self.graph
+            .send(
+                Method::PATCH,
+                &format!("/me/todo/lists/{}", tasklist_id),
+                Some(&list),
+            )
+            .await?;
+
+        if let Some(t) = self.tasklists.iter_mut().find(|t| t.id == tasklist_id) {
+            t.title = title.to_string();
+        }
+
+        Ok(())
+    }
+
+    async fn delete_tasklist(&mut self, tasklist_id: &str) -> anyhow::Result<()> {
+        self.graph
+            .send::<()>(
+                Method::DELETE,
+                &format!("/me/todo/lists/{}", tasklist_id),
+                None,
+            )
+            .await?;
+
+        self.tasklists.retain(|t| t.id != tasklist_id);
+
+        Ok(())
+    }
+
+    async fn update_task(&mut self, tasklist_id: &str, task: &Task) -> anyhow::Result<()> {
+        let url = format!("/me/todo/lists/{}/tasks/{}", tasklist_id, task.id);
+        let previous = self.get_task(tasklist_id, &task.id);
+        let body = serde_json::to_string(&task_to_graph(task, previous)?)?;
+
+        let (status, response) = self
+            .graph
+            .request(Method::PATCH, &url, task.etag.as_deref(), Some(body))
+            .await?;
+        if status == StatusCode::PRECONDITION_FAILED {
+            let remote = self.graph.send::<()>(Method::GET, &url, None).await?;
+
+            return Err(Conflict {
+                tasklist_id: tasklist_id.to_string(),
+                local: task.clone(),
+                remote: graph_to_task(serde_json::from_str(&remote)?),
+            }
+            .into());
+        }
+        check(&Method::PATCH, &url, status, &response)?;
+
+        self.load_tasklist(tasklist_id).await
+    }
+
+    async fn create_task(&mut self, tasklist_id: &str, task: &Task) -> anyhow::Result<String> {
+        let response = self
+            .graph
+            .send(
+                Method::POST,
+                &format!("/me/todo/lists/{}/tasks", tasklist_id),
+                Some(&task_to_graph(task, None)?),
+            )
+            .await?;
+        let created = graph_to_task(serde_json::from_str::<GraphTask>(&response)?);
+
+        let id = created.id.clone();
+        if let Some(t) = self.tasklists.iter_mut().find(|t| t.id == tasklist_id) {
+            t.tasks.push(created);
+        }
+
+        Ok(id)
+    }
+
+    async fn delete_task(&mut self, tasklist_id: &str, task_id: &str) -> anyhow::Result<()> {
+        self.graph
+            .send::<()>(
+                Method::DELETE,
+                &format!("/me/todo/lists/{}/tasks/{}", tasklist_id, task_id),
+                None,
+            )
+            .await?;
+
+        if let Some(t) = self.tasklists.iter_mut().find(|t| t.id == tasklist_id) {
+            t.tasks.retain(|t| t.id != task_id);
+        }
+
+        Ok(())
+    }
+
+    async fn move_task(
+        &mut self,
+        _tasklist_id: &str,
+        _task_id: &str,
+        _parent: Option<&str>,
+        _previous: Option<&str>,
+    ) -> anyhow::Result<()> {
+        anyhow::bail!("microsoft to do does not support ordering or nesting tasks")
+    }
+}
+
+fn graph_to_task(gtask: GraphTask) -> Task {
+    let status = match gtask.status.as_deref() {
+        Some("completed") => Status::Done,
+        Some(_) => Status::Todo,
+        None => Status::Unknown,
+    };
+    let due = gtask.due_date_time.as_ref().and_then(parse_due);
+    let notes = gtask
+        .body
+        .map(|b| b.content)
+        .filter(|n| !n.trim().is_empty());
+
+    Task {
+        etag: gtask.etag,
+        updated: gtask.last_modified_date_time,
+        ..Task::new(&gtask.id, status, &gtask.title, due, notes.as_deref())
+    }
+}
+
+/// Due dates are midnight in the time zone they were set in, due times are shown in local time.
+fn parse_due(due: &GraphDateTime) -> Option<TimestampType> {
+    let datetime = NaiveDateTime::parse_from_str(&due.date_time, "%Y-%m-%dT%H:%M:%S%.f").ok()?;
+    if datetime.time() == NaiveTime::MIN {
+        return Some(TimestampType::Date(datetime.date()));
+    }
+
+    // Windows time zone names are not known, their times are shown as they are.
+    let local = match due.time_zone.parse::<Tz>() {
+        Ok(tz) => tz
+            .from_local_datetime(&datetime)
+            .earliest()?
+            .with_timezone(&Local)
+            .naive_local(),
+        Err(_) => datetime,
+    };
+
+    Some(TimestampType::DateTime(local))
+}
+
+/// `previous` is the task as it was loaded, open tasks stay in progress or waiting on others
+/// unless they are reopened.
+fn task_to_graph(task: &Task, previous: Option<&Task>) -> anyhow::Result<GraphTask> {
+    let status = match (&task.status, previous.map(|p| &p.status)) {
+        (Status::Done, _) => Some(String::from("completed")),
+        (Status::Todo, Some(Status::Todo)) => None,
+        (Status::Todo, _) => Some(String::from("notStarted")),
+        (Status::Unknown, _) => None,
+    };
+    // Times are sent in UTC, dates as midnight, which is how `parse_due` reads them back.
+    let due = match &task.due {
+        Some(TimestampType::Date(date)) => Some(date.format("%Y-%m-%dT00:00:00").to_string()),
+        Some(TimestampType::DateTime(datetime)) => {
+            Local.from_local_datetime(datetime).earliest().map(|d| {
+                d.with_timezone(&Utc)
+                    .format("%Y-%m-%dT%H:%M:%S")
+                    .to_string()
+            })
+        }
+        Some(TimestampType::Time(_)) => anyhow::bail!("microsoft to do due dates need a date"),
+        None => None,
+    };
+
+    Ok(GraphTask {
+        id: task.id.clone(),
+        title: task.title.clone(),
+        status,
+        body: Some(GraphBody {
+            content: task.notes.clone().unwrap_or_default(),
+            content_type: String::from("text"),
+        }),
+        due_date_time: due.map(|date_time| GraphDateTime {
+            date_time,
+            time_zone: String::from("UTC"),
+        }),
+        last_modified_date_time: None,
+        etag: None,
+    })
+}
+
+pub async fn setup(account: &AccountCfg) -> anyhow::Result<impl Provider> {
+    let cfg = &account.microsoft;
+    let auth = match cfg.client_id.is_empty() {
+        true => None,
+        false => Some(login(cfg, account.path("cache", "microsoft_tokens.json")).await?),
+    };
+    let graph = Graph::new(&cfg.base_url, auth);
+
+    let mut provider = CachedProvider::new(
+        MicrosoftTodoProvider::new(graph),
+        account.path("cache", "microsoft.json"),
+    )?;
+    // Nothing to show yet, so the first sync (and login) happens before the ui starts.
+    if provider.is_empty() {
+        provider.sync().await?;
+    }
+
+    Ok(provider)
+}
+
+async fn login(
+    cfg: &MicrosoftCfg,
+    token_cache: PathBuf,
+) -> anyhow::Result<Authenticator<HttpsConnector<HttpConnector>>> {
+    let authority = format!(
+        "https://login.microsoftonline.com/{}/oauth2/v2.0",
+        cfg.tenant
+    );
+    let secret = ApplicationSecret {
+        client_id: cfg.client_id.clone(),
+        client_secret: cfg.client_secret.clone().unwrap_or_default(),
+        auth_uri: format!("{}/authorize", authority),
+        token_uri: format!("{}/token", authority),
+        redirect_uris: vec![String::from("http://localhost")],
+        ..Default::default()
+    };
+
+    oauth::login(secret, token_cache).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        sync::{Arc, Mutex},
+    };
+
+    use super::*;
+
+    /// Request as the mock server got it.
+    struct Received {
+        method: String,
+        path: String,
+        headers: Vec<String>,
+        body: String,
+    }
+
+    type Route = (&'static str, &'static str, u16, String);
+
+    /// Serves the routes on a local port until the test ends, returns its url and what it got.
+    fn serve(routes: impl FnOnce(&str) -> Vec<Route>) -> (String, Arc<Mutex<Vec<Received>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let routes = Arc::new(routes(&url));
+        let received = Arc::new(Mutex::new(Vec::new()));
+
+        let log = received.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let (routes, log) = (routes.clone(), log.clone());
+                std::thread::spawn(move || {
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    let mut stream = stream;
+                    // Connections are kept alive, several requests come one after another.
+                    loop {
+                        let mut line = String::new();
+                        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                            break;
+                        }
+                        let mut parts = line.split_whitespace();
+                        let method = parts.next().unwrap_or_default().to_string();
+                        let path = parts.next().unwrap_or_default().to_string();
+
+                        let mut headers = Vec::new();
+                        loop {
+                            let mut header = String::new();
+                            reader.read_line(&mut header).unwrap();
+                            match header.trim_end() {
+                                "" => break,
+                                header => headers.push(header.to_lowercase()),
+                            }
+                        }
+                        let length = headers
+                            .iter()
+                            .find_map(|h| h.strip_prefix("content-length: "))
+                            .map_or(0, |l| l.parse().unwrap());
+                        let mut body = vec![0; length];
+                        reader.read_exact(&mut body).unwrap();
+
+                        let (status, response) = routes
+                            .iter()
+                            .find(|(m, p, ..)| *m == method && *p == path)
+                            .map_or((404, String::new()), |(.., s, r)| (*s, r.clone()));
+                        log.lock().unwrap().push(Received {
+                            method,
+                            path,
+                            headers,
+                            body: String::from_utf8(body).unwrap(),
+                        });
+
+                        write!(
+                            stream,
+                            "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                            status,
+                            response.len(),
+                            response
+                        )
+                        .unwrap();
+                    }
+                });
+            }
+        });
+
+        (url, received)
+    }
+
+    fn block_on<F: std::future::Future>(f: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(f)
+    }
+
+    const TASKS: &str = r#"{"value": [{
+        "id": "t1",
+        "title": "Write report",
+        "status": "inProgress",
+        "body": {"content": "", "contentType": "text"},
+        "dueDateTime": {"dateTime": "2024-01-02T09:00:00.0000000", "timeZone": "Europe/Berlin"},
+        "@odata.etag": "W/\"1\""
+    }, {
+        "id": "t2",
+        "title": "Call",
+        "status": "completed",
+        "body": {"content": "Notes", "contentType": "text"},
+        "dueDateTime": {"dateTime": "2024-01-03T00:00:00.0000000", "timeZone": "UTC"},
+        "@odata.etag": "W/\"2\""
+    }]}"#;
+
+    fn routes(url: &str) -> Vec<Route> {
+        vec![
+            (
+                "GET",
+                "/me/todo/lists",
+                200,
+                format!(
+                    r#"{{"value": [{{"id": "l1", "displayName": "Work"}}],
+                        "@odata.nextLink": "{}/me/todo/lists?page=2"}}"#,
+                    url
+                ),
+            ),
+            (
+                "GET",
+                "/me/todo/lists?page=2",
+                200,
+                r#"{"value": [{"id": "l2", "displayName": "Home"}]}"#.to_string(),
+            ),
+            ("GET", "/me/todo/lists/l1/tasks", 200, TASKS.to_string()),
+            (
+                "GET",
+                "/me/todo/lists/l2/tasks",
+                200,
+                r#"{"value": []}"#.to_string(),
+            ),
+            ("PATCH", "/me/todo/lists/l1/tasks/t1", 200, "{}".to_string()),
+            (
+                "PATCH",
+                "/me/todo/lists/l1/tasks/t2",
+                412,
+                r#"{"error": {"message": "etag mismatch"}}"#.to_string(),
+            ),
+            (
+                "GET",
+                "/me/todo/lists/l1/tasks/t2",
+                200,
+                r#"{"id": "t2", "title": "Call mom", "status": "completed",
+                    "@odata.etag": "W/\"3\""}"#
+                    .to_string(),
+            ),
+        ]
+    }
+
+    #[test]
+    fn loads_every_page_of_tasklists() {
+        let (url, _) = serve(routes);
+        let mut provider = MicrosoftTodoProvider::new(Graph::new(&url, None));
+        block_on(provider.sync()).unwrap();
+
+        let titles = provider
+            .get_tasklists()
+            .iter()
+            .map(|t| t.title.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(titles, ["Work", "Home"]);
+
+        let tasks = &provider.get_tasklists()[0].tasks;
+        assert!(matches!(tasks[0].status, Status::Todo));
+        assert_eq!(tasks[0].etag.as_deref(), Some("W/\"1\""));
+        assert_eq!(
+            tasks[0].due,
+            Some(TimestampType::DateTime(
+                chrono_tz::Europe::Berlin
+                    .with_ymd_and_hms(2024, 1, 2, 9, 0, 0)
+                    .unwrap()
+                    .with_timezone(&Local)
+                    .naive_local()
+            ))
+        );
+        assert!(matches!(tasks[1].status, Status::Done));
+        assert_eq!(tasks[1].notes.as_deref(), Some("Notes"));
+        assert_eq!(
+            tasks[1].due,
+            Some(TimestampType::Date(
+                chrono::NaiveDate::from_ymd_opt(2024, 1, 3).unwrap()
+            ))
+        );
+    }
+
+    #[test]
+    fn updates_keep_the_status_and_send_the_etag() {
+        let (url, received) = serve(routes);
+        let mut provider = MicrosoftTodoProvider::new(Graph::new(&url, None));
+        block_on(provider.sync()).unwrap();
+
+        let task = Task {
+            title: "Write the report".to_string(),
+            ..provider.get_tasklists()[0].tasks[0].clone()
+        };
+        block_on(provider.update_task("l1", &task)).unwrap();
+
+        let received = received.lock().unwrap();
+        let patch = received.iter().find(|r| r.method == "PATCH").unwrap();
+        assert_eq!(patch.path, "/me/todo/lists/l1/tasks/t1");
+        assert!(patch.headers.contains(&"if-match: w/\"1\"".to_string()));
+
+        let body = serde_json::from_str::<serde_json::Value>(&patch.body).unwrap();
+        assert_eq!(body["title"], "Write the report");
+        assert!(body.get("status").is_none());
+        assert_eq!(body["dueDateTime"]["timeZone"], "UTC");
+        assert_eq!(
+            body["dueDateTime"]["dateTime"],
+            chrono_tz::Europe::Berlin
+                .with_ymd_and_hms(2024, 1, 2, 9, 0, 0)
+                .unwrap()
+                .with_timezone(&Utc)
+                .format("%Y-%m-%dT%H:%M:%S")
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn precondition_failures_are_conflicts() {
+        let (url, _) = serve(routes);
+        let mut provider = MicrosoftTodoProvider::new(Graph::new(&url, None));
+        block_on(provider.sync()).unwrap();
+
+        let task = provider.get_tasklists()[0].tasks[1].clone();
+        let err = block_on(provider.update_task("l1", &task)).unwrap_err();
+
+        let conflict = err.downcast::<Conflict>().unwrap();
+        assert_eq!(conflict.tasklist_id, "l1");
+        assert_eq!(conflict.local.title, "Call");
+        assert_eq!(conflict.remote.title, "Call mom");
+        assert_eq!(conflict.remote.etag.as_deref(), Some("W/\"3\""));
+    }
+
+    #[test]
+    fn due_times_near_midnight_are_labelled_on_the_local_clock() {
+        use crate::timestamps::formatter::{relative_at, Urgency};
+
+        let now = chrono::NaiveDate::from_ymd_opt(2024, 3, 10)
+            .unwrap()
+            .and_hms_opt(23, 30, 0)
+            .unwrap();
+        // Graph sends the times in UTC, they are compared with `now` once in the local zone.
+        let due = |minutes: i64| {
+            let local = now + chrono::Duration::minutes(minutes);
+            let utc = Local
+                .from_local_datetime(&local)
+                .earliest()
+                .unwrap()
+                .with_timezone(&Utc);
+            parse_due(&GraphDateTime {
+                date_time: utc.format("%Y-%m-%dT%H:%M:%S%.f").to_string(),
+                time_zone: "UTC".to_string(),
+            })
+            .unwrap()
+        };
+
+        assert_eq!(
+            relative_at(&due(20), now),
+            ("in 20 minutes".to_string(), Urgency::Upcoming)
+        );
+        assert_eq!(
+            relative_at(&due(40), now),
+            ("in 40 minutes".to_string(), Urgency::Upcoming)
+        );
+        assert_eq!(
+            relative_at(&due(-45), now),
+            ("45 minutes ago".to_string(), Urgency::Overdue)
+        );
+    }
+}
@@ -0,0 +1,883 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::NaiveDate;
+
+use crate::{
+    app::{Status, Task, Tasklist},
+    provider::{Loader, Provider},
+    timestamps::TimestampType,
+};
+
+/// Tasks without a `+project` end up in this list.
+const INBOX: &str = "Inbox";
+
+/// One task in the todo.txt format, see <https://github.com/todotxt/todo.txt>.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Line {
+    done: bool,
+    priority: Option<char>,
+    completed: Option<NaiveDate>,
+    created: Option<NaiveDate>,
+    /// Words of the title, `+projects` and `key:value` tags, in the order they are written.
+    words: Vec<String>,
+}
+
+/// What a word after the dates of a line stands for.
+enum Word<'a> {
+    Title,
+    Project(&'a str),
+    Due(NaiveDate),
+    Tag,
+}
+impl<'a> Word<'a> {
+    fn of(word: &'a str) -> Self {
+        if let Some(project) = word.strip_prefix('+').filter(|p| !p.is_empty()) {
+            Word::Project(project)
+        } else if let Some(due) = word
+            .strip_prefix("due:")
+            .and_then(|due| NaiveDate::parse_from_str(due, "%F").ok())
+        {
+            Word::Due(due)
+        } else if is_tag(word) {
+            Word::Tag
+        } else {
+            Word::Title
+        }
+    }
+}
+
+impl Line {
+    fn parse(line: &str) -> Option<Self> {
+        let mut words = line.split_whitespace().peekable();
+        words.peek()?;
+
+        let mut parsed = Line::default();
+        let date = |word: Option<&&str>| word.and_then(|w| NaiveDate::parse_from_str(w, "%F").ok());
+
+        if words.peek() == Some(&"x") {
+            words.next();
+            parsed.done = true;
+            parsed.completed = date(words.peek());
+            if parsed.completed.is_some() {
+                words.next();
+            }
+        } else if let Some(priority) = words.peek().and_then(|w| parse_priority(w)) {
+            words.next();
+            parsed.priority = Some(priority);
+        }
+        parsed.created = date(words.peek());
+        if parsed.created.is_some() {
+            words.next();
+        }
+        parsed.words = words.map(|w| w.to_string()).collect();
+
+        Some(parsed)
+    }
+
+    fn format(&self) -> String {
+        let mut words = Vec::new();
+
+        if self.done {
+            words.push("x".to_string());
+            words.extend(self.completed.map(|d| d.format("%F").to_string()));
+        } else if let Some(priority) = self.priority {
+            words.push(format!("({})", priority));
+        }
+        words.extend(self.created.map(|d| d.format("%F").to_string()));
+        words.extend(self.words.iter().cloned());
+
+        words.join(" ")
+    }
+
+    fn title(&self) -> String {
+        self.words
+            .iter()
+            .filter(|w| matches!(Word::of(w), Word::Title))
+            .map(|w| w.as_str())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn projects(&self) -> impl Iterator<Item = &str> {
+        self.words.iter().filter_map(|w| match Word::of(w) {
+            Word::Project(project) => Some(project),
+            _ => None,
+        })
+    }
+
+    fn project(&self) -> &str {
+        self.projects().next().unwrap_or(INBOX)
+    }
+
+    fn due(&self) -> Option<NaiveDate> {
+        self.words.iter().find_map(|w| match Word::of(w) {
+            Word::Due(due) => Some(due),
+            _ => None,
+        })
+    }
+
+    fn to_task(&self, id: &str, position: usize) -> Task {
+        Task {
+            position: Some(format!("{:020}", position)),
+            ..Task::new(
+                id,
+                if self.done {
+                    Status::Done
+                } else {
+                    Status::Todo
+                },
+                &self.title(),
+                self.due().map(TimestampType::Date),
+                None,
+            )
+        }
+    }
+
+    /// Applies the fields a `Task` knows about, keeping priority, projects, tags and the order
+    /// of the words.
+    fn update(&mut self, task: &Task) -> anyhow::Result<()> {
+        if task.notes.as_deref().is_some_and(|n| !n.is_empty()) {
+            anyhow::bail!("todo.txt tasks can not have notes");
+        }
+        let due = match &task.due {
+            Some(TimestampType::Date(date)) => Some(*date),
+            Some(TimestampType::DateTime(datetime)) => Some(datetime.date()),
+            Some(TimestampType::Time(_)) => anyhow::bail!("todo.txt due dates can not be a time"),
+            None => None,
+        };
+
+        let done = matches!(task.status, Status::Done);
+        if done && !self.done {
+            self.completed = Some(chrono::Local::now().date_naive());
+            // Completed tasks have no priority, a `pri:` tag keeps it for when they are reopened.
+            if let Some(priority) = self.priority.take() {
+                self.words.push(format!("pri:{}", priority));
+            }
+        } else if !done && self.done {
+            self.completed = None;
+            let priority = self.words.iter().position(|w| {
+                w.strip_prefix("pri:")
+                    .is_some_and(|p| p.len() == 1 && p.chars().all(|c| c.is_ascii_uppercase()))
+            });
+            if let Some(i) = priority {
+                self.priority = self.words.remove(i).chars().last();
+            }
+        }
+        self.done = done;
+
+        if task.title != self.title() {
+            self.set_title(&task.title);
+        }
+        if due != self.due() {
+            self.set_due(due);
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the title words, in place when there are as many, otherwise the new title goes
+    /// where the old one started.
+    fn set_title(&mut self, title: &str) {
+        let positions = (0..self.words.len())
+            .filter(|i| matches!(Word::of(&self.words[*i]), Word::Title))
+            .collect::<Vec<_>>();
+        let words = title.split_whitespace().collect::<Vec<_>>();
+        if positions.len() == words.len() {
+            for (i, word) in positions.into_iter().zip(words) {
+                self.words[i] = word.to_string();
+            }
+            return;
+        }
+
+        let start = positions.first().copied().unwrap_or(0);
+        self.words.retain(|w| !matches!(Word::of(w), Word::Title));
+        self.words.splice(
+            start..start,
+            title.split_whitespace().map(|w| w.to_string()),
+        );
+    }
+
+    fn set_due(&mut self, due: Option<NaiveDate>) {
+        let i = self
+            .words
+            .iter()
+            .position(|w| matches!(Word::of(w), Word::Due(_)));
+        let word = due.map(|due| format!("due:{}", due.format("%F")));
+        match (i, word) {
+            (Some(i), Some(word)) => self.words[i] = word,
+            (Some(i), None) => {
+                self.words.remove(i);
+            }
+            (None, Some(word)) => self.words.push(word),
+            (None, None) => {}
+        }
+    }
+
+    fn rename_project(&mut self, from: &str, to: &str) {
+        if from == INBOX && self.projects().next().is_none() {
+            self.words.push(format!("+{}", to));
+        }
+        for word in self.words.iter_mut() {
+            if matches!(Word::of(word), Word::Project(project) if project == from) {
+                *word = format!("+{}", to);
+            }
+        }
+    }
+}
+
+fn parse_priority(word: &str) -> Option<char> {
+    let mut chars = word.chars();
+    match (chars.next(), chars.next(), chars.next(), chars.next()) {
+        (Some('('), Some(p), Some(')'), None) if p.is_ascii_uppercase() => Some(p),
+        _ => None,
+    }
+}
+
+fn is_tag(word: &str) -> bool {
+    match word.split_once(':') {
+        Some((key, value)) => {
+            !key.is_empty() && !value.is_empty() && !key.contains('/') && !value.starts_with('/')
+        }
+        None => false,
+    }
+}
+
+/// Projects can not contain whitespace.
+fn project_name(title: &str) -> String {
+    title.split_whitespace().collect::<Vec<_>>().join("_")
+}
+
+/// A line of the file with the id of its task.
+#[derive(Clone, Debug)]
+struct Entry {
+    id: String,
+    /// The line as it is in the file, written back as it is unless its task changes.
+    raw: String,
+    /// `None` for blank lines.
+    line: Option<Line>,
+}
+impl Entry {
+    fn new(raw: String, taken: &HashSet<String>) -> Self {
+        Self {
+            id: line_id(&raw, taken),
+            line: Line::parse(&raw),
+            raw,
+        }
+    }
+
+    /// Replaces the task, the line is only rewritten when it actually changed.
+    fn set(&mut self, line: Line) {
+        if self.line.as_ref() != Some(&line) {
+            self.raw = line.format();
+            self.line = Some(line);
+        }
+    }
+}
+
+/// Id of a line, derived from its text when tudo first reads it and kept while tudo changes
+/// it, so ids do not shift when other lines are added, moved or removed.
+fn line_id(raw: &str, taken: &HashSet<String>) -> String {
+    // FNV-1a, which unlike the std hasher is the same in every build.
+    let hash = raw.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+
+    let id = format!("{:016x}", hash);
+    (1..)
+        .map(|n| match n {
+            1 => id.clone(),
+            n => format!("{}-{}", id, n),
+        })
+        .find(|id| !taken.contains(id))
+        .unwrap_or(id)
+}
+
+/// Splits the content into entries, lines that are still the same as a `known` entry keep its id.
+fn read_entries(content: &str, known: &[Entry]) -> Vec<Entry> {
+    let mut ids: HashMap<&str, Vec<&str>> = HashMap::new();
+    for entry in known.iter().rev() {
+        ids.entry(&entry.raw).or_default().push(&entry.id);
+    }
+
+    let lines = content
+        .lines()
+        .map(|raw| (raw, ids.get_mut(raw).and_then(|ids| ids.pop())))
+        .collect::<Vec<_>>();
+    let mut taken = lines
+        .iter()
+        .filter_map(|(_, id)| id.map(|id| id.to_string()))
+        .collect::<HashSet<_>>();
+
+    lines
+        .into_iter()
+        .map(|(raw, id)| match id {
+            Some(id) => Entry {
+                id: id.to_string(),
+                raw: raw.to_string(),
+                line: Line::parse(raw),
+            },
+            None => {
+                let entry = Entry::new(raw.to_string(), &taken);
+                taken.insert(entry.id.clone());
+                entry
+            }
+        })
+        .collect()
+}
+
+/// Groups the lines into tasklists by their first project, in the order of the file.
+fn to_tasklists(entries: &[Entry], empty: &[String]) -> Vec<Tasklist> {
+    let mut tasklists: Vec<Tasklist> = Vec::new();
+
+    for (i, entry) in entries.iter().enumerate() {
+        let Some(line) = &entry.line else {
+            continue;
+        };
+        let task = line.to_task(&entry.id, i);
+        let project = line.project();
+        match tasklists.iter_mut().find(|t| t.id == project) {
+            Some(tasklist) => tasklist.tasks.push(task),
+            None => tasklists.push(Tasklist::new(
+                project.to_string(),
+                project.to_string(),
+                &[task],
+            )),
+        }
+    }
+    for project in empty {
+        if !tasklists.iter().any(|t| &t.id == project) {
+            tasklists.push(Tasklist::new(project.clone(), project.clone(), &[]));
+        }
+    }
+
+    tasklists
+}
+
+/// Formats all tasklists as todo.txt, with the tasklist title as project.
+pub fn export(tasklists: &[Tasklist]) -> String {
+    tasklists
+        .iter()
+        .flat_map(|tasklist| {
+            tasklist.tasks.iter().map(|task| {
+                let mut line = Line {
+                    words: match tasklist.title.as_str() {
+                        INBOX => Vec::new(),
+                        title => vec![format!("+{}", project_name(title))],
+                    },
+                    ..Default::default()
+                };
+                // Notes and time of day have no place in todo.txt.
+                let task = Task {
+                    notes: None,
+                    due: match &task.due {
+                        Some(TimestampType::Time(_)) => None,
+                        due => due.clone(),
+                    },
+                    ..task.clone()
+                };
+                line.update(&task).map(|_| line.format())
+            })
+        })
+        .filter_map(|line| line.ok())
+        .map(|line| line + "\n")
+        .collect()
+}
+
+/// Parses todo.txt content into tasklists, one per project.
+pub fn import(content: &str) -> Vec<Tasklist> {
+    to_tasklists(&read_entries(content, &[]), &[])
+}
+
+/// Reads and writes a todo.txt file, mapping projects to tasklists.
+pub struct TodoTxtProvider {
+    path: PathBuf,
+
+    entries: Vec<Entry>,
+    /// Whether the file ends with a newline, kept when writing it.
+    newline_at_end: bool,
+    /// Tasklists created in tudo that have no tasks yet, so no line mentions them.
+    empty: Vec<String>,
+    tasklists: Vec<Tasklist>,
+}
+
+impl TodoTxtProvider {
+    fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            entries: Vec::new(),
+            newline_at_end: true,
+            empty: Vec::new(),
+            tasklists: Vec::new(),
+        }
+    }
+
+    fn load(&mut self) -> anyhow::Result<()> {
+        let content = match std::fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err.into()),
+        };
+        self.entries = read_entries(&content, &self.entries);
+        self.newline_at_end = content.is_empty() || content.ends_with('\n');
+        self.tasklists = to_tasklists(&self.entries, &self.empty);
+
+        Ok(())
+    }
+
+    /// Rereads the file before changing it, so changes other programs made since it was last
+    /// read are kept, and writes it back.
+    fn edit<T>(
+        &mut self,
+        change: impl FnOnce(&mut Vec<Entry>) -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        self.load()?;
+        let result = change(&mut self.entries)?;
+
+        let mut content = self
+            .entries
+            .iter()
+            .map(|e| e.raw.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        if self.newline_at_end && !self.entries.is_empty() {
+            content.push('\n');
+        }
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, content)?;
+
+        let entries = &self.entries;
+        self.empty.retain(|p| {
+            !entries
+                .iter()
+                .any(|e| e.line.as_ref().is_some_and(|l| l.project() == p))
+        });
+        self.tasklists = to_tasklists(&self.entries, &self.empty);
+
+        Ok(result)
+    }
+}
+
+fn entry_index(entries: &[Entry], task_id: &str) -> anyhow::Result<usize> {
+    entries
+        .iter()
+        .position(|e| e.id == task_id && e.line.is_some())
+        .ok_or(anyhow::anyhow!("task with id {} not found", task_id))
+}
+
+#[async_trait::async_trait]
+impl Provider for TodoTxtProvider {
+    fn get_tasklists(&self) -> &Vec<Tasklist> {
+        &self.tasklists
+    }
+
+    async fn sync(&mut self) -> anyhow::Result<()> {
+        self.load()
+    }
+
+    fn watcher(&self) -> Option<Loader> {
+        let path = self.path.clone();
+        let seen = Arc::new(Mutex::new(modified(&self.path)));
+
+        Some(Box::new(move || {
+            let path = path.clone();
+            let seen = seen.clone();
+
+            Box::pin(async move {
+                loop {
+                    let current = modified(&path);
+                    if let Ok(mut seen) = seen.lock() {
+                        if *seen != current {
+                            *seen = current;
+                            break;
+                        }
+                    }
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+
+                Ok(import(&std::fs::read_to_string(&path)?))
+            })
+        }))
+    }
+
+    fn set_tasklists(&mut self, _tasklists: Vec<Tasklist>) {
+        // The ids of the watcher's tasklists only depend on the text of each line, reread the
+        // file instead so lines tudo changed keep theirs.
+        let _ = self.load();
+    }
+
+    async fn create_tasklist(&mut self, title: &str) -> anyhow::Result<String> {
+        let project = project_name(title);
+        if !self.tasklists.iter().any(|t| t.id == project) {
+            self.empty.push(project.clone());
+            self.tasklists = to_tasklists(&self.entries, &self.empty);
+        }
+
+        Ok(project)
+    }
+
+    async fn rename_tasklist(&mut self, tasklist_id: &str, title: &str) -> anyhow::Result<()> {
+        let project = project_name(title);
+        for p in self.empty.iter_mut().filter(|p| *p == tasklist_id) {
+            *p = project.clone();
+        }
+
+        self.edit(|entries| {
+            for entry in entries.iter_mut() {
+                if let Some(mut line) = entry.line.clone() {
+                    line.rename_project(tasklist_id, &project);
+                    entry.set(line);
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    async fn delete_tasklist(&mut self, tasklist_id: &str) -> anyhow::Result<()> {
+        self.empty.retain(|p| p != tasklist_id);
+
+        self.edit(|entries| {
+            entries.retain(|e| e.line.as_ref().is_none_or(|l| l.project() != tasklist_id));
+
+            Ok(())
+        })
+    }
+
+    async fn update_task(&mut self, _tasklist_id: &str, task: &Task) -> anyhow::Result<()> {
+        self.edit(|entries| {
+            let i = entry_index(entries, &task.id)?;
+            let entry = &mut entries[i];
+            let mut line = entry.line.clone().unwrap_or_default();
+            line.update(task)?;
+            entry.set(line);
+
+            Ok(())
+        })
+    }
+
+    async fn create_task(&mut self, tasklist_id: &str, task: &Task) -> anyhow::Result<String> {
+        if task.parent.is_some() {
+            anyhow::bail!("todo.txt has no subtasks");
+        }
+
+        let mut line = Line {
+            created: Some(chrono::Local::now().date_naive()),
+            words: match tasklist_id {
+                INBOX => Vec::new(),
+                project => vec![format!("+{}", project)],
+            },
+            ..Default::default()
+        };
+        line.update(task)?;
+
+        self.edit(|entries| {
+            let taken = entries.iter().map(|e| e.id.clone()).collect();
+            let entry = Entry::new(line.format(), &taken);
+            let id = entry.id.clone();
+            // New tasks go first in their tasklist, like the worker shows them right away.
+            let first = entries
+                .iter()
+                .position(|e| e.line.as_ref().is_some_and(|l| l.project() == tasklist_id));
+            entries.insert(first.unwrap_or(entries.len()), entry);
+
+            Ok(id)
+        })
+    }
+
+    async fn delete_task(&mut self, _tasklist_id: &str, task_id: &str) -> anyhow::Result<()> {
+        self.edit(|entries| {
+            let i = entry_index(entries, task_id)?;
+            entries.remove(i);
+
+            Ok(())
+        })
+    }
+
+    async fn move_task(
+        &mut self,
+        tasklist_id: &str,
+        task_id: &str,
+        parent: Option<&str>,
+        previous: Option<&str>,
+    ) -> anyhow::Result<()> {
+        if parent.is_some() {
+            anyhow::bail!("todo.txt has no subtasks");
+        }
+
+        self.edit(|entries| {
+            let i = entry_index(entries, task_id)?;
+            let entry = entries.remove(i);
+            let target = match previous {
+                Some(previous) => entry_index(entries, previous)? + 1,
+                None => entries
+                    .iter()
+                    .position(|e| e.line.as_ref().is_some_and(|l| l.project() == tasklist_id))
+                    .unwrap_or(entries.len()),
+            };
+            entries.insert(target, entry);
+
+            Ok(())
+        })
+    }
+}
+
+fn modified(path: &Path) -> Option<std::time::SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+pub fn setup(path: &str) -> anyhow::Result<TodoTxtProvider> {
+    let mut provider = TodoTxtProvider::new(PathBuf::from(path));
+    provider.load()?;
+
+    Ok(provider)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    /// A provider on a fresh file with the content, removed again when the test is done.
+    struct TempFile(PathBuf);
+    impl TempFile {
+        fn new(name: &str, content: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "tudo-todotxt-{}-{}.txt",
+                std::process::id(),
+                name
+            ));
+            std::fs::write(&path, content).unwrap();
+            Self(path)
+        }
+
+        fn provider(&self) -> TodoTxtProvider {
+            setup(self.0.to_str().unwrap()).unwrap()
+        }
+
+        fn read(&self) -> String {
+            std::fs::read_to_string(&self.0).unwrap()
+        }
+    }
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn task<'a>(provider: &'a TodoTxtProvider, title: &str) -> &'a Task {
+        provider
+            .get_tasklists()
+            .iter()
+            .flat_map(|t| t.tasks.iter())
+            .find(|t| t.title == title)
+            .unwrap()
+    }
+
+    #[test]
+    fn parse_format_round_trip() {
+        for line in [
+            "Call mom",
+            "(A) 2024-01-02 Call mom +Family @phone due:2024-02-01",
+            "x 2024-01-03 2024-01-01 Pay rent +Home rec:1m pri:B",
+            "x Done without dates",
+            "+Work starts with a project",
+            "Odd due:tomorrow stays a tag http://example.com",
+        ] {
+            assert_eq!(Line::parse(line).unwrap().format(), line);
+        }
+
+        assert_eq!(Line::parse("   "), None);
+    }
+
+    #[test]
+    fn parse_fields() {
+        let line =
+            Line::parse("(A) 2024-01-02 Call +Family mom @phone due:2024-02-01 +Other").unwrap();
+
+        assert_eq!(line.priority, Some('A'));
+        assert_eq!(line.created, NaiveDate::from_ymd_opt(2024, 1, 2));
+        assert_eq!(line.title(), "Call mom @phone");
+        assert_eq!(line.project(), "Family");
+        assert_eq!(line.due(), NaiveDate::from_ymd_opt(2024, 2, 1));
+
+        let line = Line::parse("x 2024-01-03 2024-01-01 Pay rent").unwrap();
+        assert!(line.done);
+        assert_eq!(line.completed, NaiveDate::from_ymd_opt(2024, 1, 3));
+        assert_eq!(line.created, NaiveDate::from_ymd_opt(2024, 1, 1));
+        assert_eq!(line.project(), INBOX);
+    }
+
+    #[test]
+    fn update_keeps_the_order_of_words() {
+        let mut line = Line::parse("(B) Call mom +Family due:2024-02-01 @phone").unwrap();
+        let task = Task {
+            title: "Call dad @phone".to_string(),
+            due: Some(TimestampType::Date(
+                NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+            )),
+            ..line.to_task("id", 0)
+        };
+        line.update(&task).unwrap();
+
+        assert_eq!(line.format(), "(B) Call dad +Family due:2024-03-01 @phone");
+
+        line.set_title("Only this");
+        assert_eq!(line.format(), "(B) Only this +Family due:2024-03-01");
+    }
+
+    #[test]
+    fn update_keeps_the_priority_of_completed_tasks() {
+        let mut line = Line::parse("(A) Call mom +Family").unwrap();
+        let task = line.to_task("id", 0);
+
+        line.update(&Task {
+            status: Status::Done,
+            ..task.clone()
+        })
+        .unwrap();
+        assert!(line.done);
+        assert_eq!(line.priority, None);
+        assert!(line.format().ends_with("Call mom +Family pri:A"));
+
+        line.update(&task).unwrap();
+        assert_eq!(line.format(), "(A) Call mom +Family");
+    }
+
+    #[test]
+    fn unchanged_lines_are_written_as_they_are() {
+        let file = TempFile::new("unchanged", "  (B)  spaced   line\n\nOther +Work\n");
+        let mut provider = file.provider();
+
+        let other = task(&provider, "Other").clone();
+        block_on(provider.update_task(
+            "Work",
+            &Task {
+                title: "Renamed".to_string(),
+                ..other
+            },
+        ))
+        .unwrap();
+
+        assert_eq!(file.read(), "  (B)  spaced   line\n\nRenamed +Work\n");
+    }
+
+    #[test]
+    fn ids_stay_when_other_lines_change() {
+        let file = TempFile::new("ids", "First\nSecond\nSecond\n");
+        let mut provider = file.provider();
+
+        let first = task(&provider, "First").id.clone();
+        let ids = provider.tasklists[0]
+            .tasks
+            .iter()
+            .map(|t| t.id.clone())
+            .collect::<Vec<_>>();
+        assert_ne!(ids[1], ids[2]);
+
+        block_on(provider.delete_task(INBOX, &first)).unwrap();
+        let remaining = provider.tasklists[0]
+            .tasks
+            .iter()
+            .map(|t| t.id.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(remaining, ids[1..]);
+
+        // A line changed by tudo keeps its id.
+        let second = provider.tasklists[0].tasks[0].clone();
+        block_on(provider.update_task(
+            INBOX,
+            &Task {
+                status: Status::Done,
+                ..second.clone()
+            },
+        ))
+        .unwrap();
+        block_on(provider.sync()).unwrap();
+        assert_eq!(provider.tasklists[0].tasks[0].id, second.id);
+        assert!(matches!(
+            provider.tasklists[0].tasks[0].status,
+            Status::Done
+        ));
+    }
+
+    #[test]
+    fn changes_made_elsewhere_are_kept() {
+        let file = TempFile::new("elsewhere", "First\n");
+        let mut provider = file.provider();
+        let first = task(&provider, "First").clone();
+
+        std::fs::write(&file.0, "Added elsewhere\nFirst\n").unwrap();
+        block_on(provider.update_task(
+            INBOX,
+            &Task {
+                title: "Changed".to_string(),
+                ..first
+            },
+        ))
+        .unwrap();
+
+        assert_eq!(file.read(), "Added elsewhere\nChanged\n");
+    }
+
+    #[test]
+    fn missing_tasks_are_not_changed() {
+        let file = TempFile::new("missing", "First\n");
+        let mut provider = file.provider();
+        let first = task(&provider, "First").clone();
+
+        std::fs::write(&file.0, "Replaced elsewhere\n").unwrap();
+        assert!(block_on(provider.delete_task(INBOX, &first.id)).is_err());
+
+        assert_eq!(file.read(), "Replaced elsewhere\n");
+    }
+
+    #[test]
+    fn move_task_after_previous() {
+        let file = TempFile::new("move", "A\nB\nC\n");
+        let mut provider = file.provider();
+        let a = task(&provider, "A").id.clone();
+        let c = task(&provider, "C").id.clone();
+
+        block_on(provider.move_task(INBOX, &a, None, Some(&c))).unwrap();
+        assert_eq!(file.read(), "B\nC\nA\n");
+
+        block_on(provider.move_task(INBOX, &a, None, None)).unwrap();
+        assert_eq!(file.read(), "A\nB\nC\n");
+    }
+
+    #[test]
+    fn created_tasks_go_first_in_their_project() {
+        let file = TempFile::new("create", "Inbox task\nA +Work\nB +Work\n");
+        let mut provider = file.provider();
+
+        let new = Task::new("", Status::Todo, "New", None, None);
+        block_on(provider.create_task("Work", &new)).unwrap();
+
+        let lines = file.read().lines().map(String::from).collect::<Vec<_>>();
+        assert_eq!(lines[0], "Inbox task");
+        assert!(lines[1].ends_with(" New +Work"));
+        assert_eq!(lines[2..], ["A +Work", "B +Work"]);
+
+        let work = provider.get_tasklist("Work").unwrap();
+        let titles = work
+            .tasks
+            .iter()
+            .map(|t| t.title.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(titles, ["New", "A", "B"]);
+    }
+}
@@ -1,70 +1,388 @@
-use crate::app::App;
+use crate::app::{App, Mode, Move, Tasklist};
 use crate::components;
-use std::{
-    io,
-    time::{Duration, Instant},
-};
+use crate::config::{self, Cfg};
+use crate::keymap::{Action, Keymap};
+use crate::provider::Provider;
+use crate::terminal;
+use crate::theme::Theme;
+use crate::worker::{Done, Worker};
+use std::{io, sync::atomic::Ordering, time::Duration};
 
-use crossterm::{
-    event::{self, Event, KeyCode},
-    terminal::enable_raw_mode,
-};
+use tokio::sync::mpsc;
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
 use tui::{
     backend::{Backend, CrosstermBackend},
     Terminal,
 };
 
-pub async fn run(tick_rate: Duration, app: App) -> anyhow::Result<()> {
-    enable_raw_mode()?;
+/// Everything the event loop reacts to, sent by the threads and tasks feeding it.
+enum Update {
+    Key(KeyEvent),
+    /// The terminal was resized.
+    Redraw,
+    Tick,
+    /// Tasklists fetched by the loader or watcher, with the job generation they were fetched at.
+    Refresh(u64, anyhow::Result<Vec<Tasklist>>),
+    /// A provider call finished.
+    Done(Done),
+    Quit,
+}
+
+pub async fn run(
+    tick_rate: Duration,
+    refresh_interval: Duration,
+    provider: Box<dyn Provider>,
+    cfg: &Cfg,
+    keymap: Keymap,
+    theme: Theme,
+    errors: Vec<anyhow::Error>,
+) -> anyhow::Result<()> {
+    let (tx, rx) = mpsc::unbounded_channel();
+
+    let loader = provider.loader().filter(|_| !refresh_interval.is_zero());
+    let watcher = provider.watcher();
+    // Provider calls run in the background, the app only sees their results.
+    let (worker, mut done) = Worker::spawn(provider);
+
+    // Results fetched while a job ran are dropped, they could undo its change.
+    if let Some(loader) = loader {
+        let tx = tx.clone();
+        let generation = worker.generation();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(refresh_interval).await;
+                let started = generation.load(Ordering::SeqCst);
+                if tx.send(Update::Refresh(started, loader().await)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    // Watchers wait for a change and only then read the tasklists.
+    if let Some(watcher) = watcher {
+        let tx = tx.clone();
+        let generation = worker.generation();
+        tokio::spawn(async move {
+            loop {
+                let tasklists = watcher().await;
+                let started = generation.load(Ordering::SeqCst);
+                if tx.send(Update::Refresh(started, tasklists)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    {
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            while let Some(done) = done.recv().await {
+                if tx.send(Update::Done(done)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    let mut signals = terminal::signals();
+    {
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            if signals.recv().await.is_some() {
+                let _ = tx.send(Update::Quit);
+            }
+        });
+    }
+    {
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(tick_rate);
+            loop {
+                ticks.tick().await;
+                if tx.send(Update::Tick).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    let guard = terminal::Guard::new()?;
     let stdout = io::stdout();
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
     terminal.clear()?;
 
-    let res = run_app(&mut terminal, app, tick_rate).await;
+    // Reading the terminal blocks, it gets a thread of its own.
+    std::thread::spawn(move || loop {
+        let update = match event::read() {
+            Ok(Event::Key(key)) => Update::Key(key),
+            Ok(Event::Resize(..)) => Update::Redraw,
+            Ok(_) => continue,
+            Err(_) => break,
+        };
+        if tx.send(update).is_err() {
+            break;
+        }
+    });
 
-    if let Err(err) = res {
-        println!("{:?}", err)
+    let mut app = App::new(worker, cfg);
+    // Without the saved view settings the tasklists are still usable, just shown the default way.
+    match config::load_tasklist_cfgs() {
+        Ok(tasklists) => app.tasklists.extend(tasklists),
+        Err(err) => app
+            .notifications
+            .error(&err.context("could not read the view settings")),
+    }
+    // Errors from before the tui started, like accounts that could not be set up.
+    for err in errors {
+        app.notifications.error(&err);
     }
 
-    Ok(())
+    let res = run_app(&mut terminal, app, keymap, &theme, rx).await;
+
+    // Errors are reported by main, after the terminal is back to normal.
+    drop(terminal);
+    drop(guard);
+
+    res
 }
 
 async fn run_app<B: Backend>(
     terminal: &mut Terminal<B>,
     mut app: App,
-    tick_rate: Duration,
+    mut keymap: Keymap,
+    theme: &Theme,
+    mut updates: mpsc::UnboundedReceiver<Update>,
 ) -> anyhow::Result<()> {
-    let mut last_tick = Instant::now();
+    // The cached tasklists are shown while the first sync runs.
+    if let Err(err) = app.sync() {
+        app.notifications.error(&err);
+    }
+
     loop {
-        terminal.draw(|f| components::ui(f, &mut app))?;
-
-        let timeout = tick_rate
-            .checked_sub(last_tick.elapsed())
-            .unwrap_or_else(|| Duration::from_secs(0));
-        if crossterm::event::poll(timeout)? {
-            if let Event::Key(key) = event::read()? {
-                if let Err(err) = match key.code {
-                    KeyCode::Char('q') => Ok(app.quit()),
-                    KeyCode::Char('l') | KeyCode::Right => Ok(app.tasklists_next()),
-                    KeyCode::Char('h') | KeyCode::Left => Ok(app.tasklists_previous()),
-                    KeyCode::Char('j') | KeyCode::Down => Ok(app.tasks_next()),
-                    KeyCode::Char('k') | KeyCode::Up => Ok(app.tasks_previous()),
-                    KeyCode::Enter => app.toggle_task_state().await,
-                    _ => Ok(()),
-                } {
-                    // TODO: print errors nicely
-                    print!("{:?}", err)
+        // The terminal is already restored and shows the panic, drawing would cover it.
+        if terminal::panicked() {
+            anyhow::bail!("tudo stopped after a crash, changes not saved yet may be lost");
+        }
+        terminal.draw(|f| components::ui(f, &mut app, theme))?;
+
+        let update = match updates.recv().await {
+            Some(update) => update,
+            None => return Ok(()),
+        };
+        match update {
+            Update::Key(key) => {
+                let res = match app.mode {
+                    Mode::Normal => match keymap.press(key) {
+                        Some(action) => perform(&mut app, action),
+                        None => Ok(()),
+                    },
+                    Mode::TaskForm(_) => handle_task_form_key(&mut app, key),
+                    Mode::ConfirmDelete(..) | Mode::ConfirmDeleteTasklist(_) => {
+                        handle_confirm_delete_key(&mut app, key)
+                    }
+                    Mode::TasklistForm(_) => handle_tasklist_form_key(&mut app, key),
+                    Mode::MoveToList(_) => handle_move_to_list_key(&mut app, key),
+                    Mode::Conflict(_) => handle_conflict_key(&mut app, key),
+                    Mode::Search(_) => handle_search_key(&mut app, key),
+                    Mode::ErrorLog(_) => handle_error_log_key(&mut app, key),
+                };
+                if let Err(err) = res {
+                    app.notifications.error(&err);
                 }
             }
+            Update::Redraw => {}
+            Update::Tick => app.on_tick(),
+            Update::Refresh(started, tasklists) => app.refresh(started, tasklists),
+            Update::Done(done) => app.finish(done),
+            Update::Quit => app.quit(),
         }
 
-        if last_tick.elapsed() >= tick_rate {
-            app.on_tick();
-            last_tick = Instant::now();
-        }
         if app.should_quit {
             return Ok(());
         }
     }
 }
+
+fn perform(app: &mut App, action: Action) -> anyhow::Result<()> {
+    match action {
+        Action::Quit => app.quit(),
+        Action::Sync => return app.sync(),
+        Action::TasklistsNext => app.tasklists_next(),
+        Action::TasklistsPrevious => app.tasklists_previous(),
+        Action::TasksNext => app.tasks_next(),
+        Action::TasksPrevious => app.tasks_previous(),
+        Action::TasksFirst => app.tasks_first(),
+        Action::TasksLast => app.tasks_last(),
+        Action::ToggleTask => return app.toggle_task_state(),
+        Action::AddTask => app.open_task_form(),
+        Action::EditTask => app.open_edit_form(),
+        Action::DeleteTask => app.confirm_delete(),
+        Action::UndoDelete => return app.undo_delete(),
+        Action::MoveDown => return app.move_task(Move::Down),
+        Action::MoveUp => return app.move_task(Move::Up),
+        Action::Indent => return app.move_task(Move::Indent),
+        Action::Outdent => return app.move_task(Move::Outdent),
+        Action::MoveToList => app.open_move_to_list(),
+        Action::Collapse => app.collapse_task(),
+        Action::Expand => app.expand_task(),
+        Action::Search => app.open_search(),
+        Action::ToggleCompleted => return app.toggle_completed(),
+        Action::NextSort => return app.next_sort(),
+        Action::NextFilter => return app.next_filter(),
+        Action::AddTasklist => app.open_tasklist_form(),
+        Action::RenameTasklist => app.open_rename_tasklist_form(),
+        Action::DeleteTasklist => app.confirm_delete_tasklist(),
+        Action::ErrorLog => app.open_error_log(),
+    }
+
+    Ok(())
+}
+
+fn handle_task_form_key(app: &mut App, key: KeyEvent) -> anyhow::Result<()> {
+    let submit = match key.code {
+        KeyCode::Char('s') => key.modifiers.contains(KeyModifiers::CONTROL),
+        KeyCode::Enter => !app.task_form().is_some_and(|form| form.wants_newline()),
+        _ => false,
+    };
+
+    match key.code {
+        KeyCode::Esc => app.cancel(),
+        _ if submit => return app.submit_task_form(),
+        _ => {
+            if let Some(form) = app.task_form() {
+                match key.code {
+                    KeyCode::Tab => form.focus_next(),
+                    KeyCode::BackTab => form.focus_previous(),
+                    KeyCode::Up => form.up(),
+                    KeyCode::Down => form.down(),
+                    KeyCode::Enter => form.focused().insert('\n'),
+                    KeyCode::Left => form.focused().left(),
+                    KeyCode::Right => form.focused().right(),
+                    KeyCode::Home => form.focused().home(),
+                    KeyCode::End => form.focused().end(),
+                    KeyCode::Backspace => form.focused().backspace(),
+                    KeyCode::Delete => form.focused().delete(),
+                    // Shortcuts that are not handled are not text.
+                    KeyCode::Char(_)
+                        if key
+                            .modifiers
+                            .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => {}
+                    KeyCode::Char(c) => form.focused().insert(c),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_confirm_delete_key(app: &mut App, key: KeyEvent) -> anyhow::Result<()> {
+    match key.code {
+        KeyCode::Char('y') | KeyCode::Enter => {
+            return match app.mode {
+                Mode::ConfirmDeleteTasklist(_) => app.delete_tasklist(),
+                _ => app.delete_task(),
+            }
+        }
+        KeyCode::Char('n') | KeyCode::Esc => app.cancel(),
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn handle_tasklist_form_key(app: &mut App, key: KeyEvent) -> anyhow::Result<()> {
+    match key.code {
+        KeyCode::Esc => app.cancel(),
+        KeyCode::Enter => return app.submit_tasklist_form(),
+        _ => {
+            if let Some(form) = app.tasklist_form() {
+                match key.code {
+                    KeyCode::Left => form.title.left(),
+                    KeyCode::Right => form.title.right(),
+                    KeyCode::Home => form.title.home(),
+                    KeyCode::End => form.title.end(),
+                    KeyCode::Backspace => form.title.backspace(),
+                    KeyCode::Delete => form.title.delete(),
+                    KeyCode::Char(c) => form.title.insert(c),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_move_to_list_key(app: &mut App, key: KeyEvent) -> anyhow::Result<()> {
+    match key.code {
+        KeyCode::Esc => app.cancel(),
+        KeyCode::Enter => return app.move_to_list(),
+        KeyCode::Char('j') | KeyCode::Down => app.move_to_list_next(),
+        KeyCode::Char('k') | KeyCode::Up => app.move_to_list_previous(),
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn handle_conflict_key(app: &mut App, key: KeyEvent) -> anyhow::Result<()> {
+    match key.code {
+        KeyCode::Char('l') => return app.keep_local(),
+        KeyCode::Char('r') | KeyCode::Esc => return app.keep_remote(),
+        KeyCode::Char('e') => app.merge_conflict(),
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn handle_search_key(app: &mut App, key: KeyEvent) -> anyhow::Result<()> {
+    let control = key.modifiers.contains(KeyModifiers::CONTROL);
+
+    match key.code {
+        KeyCode::Esc => app.cancel(),
+        KeyCode::Enter => app.jump_to_result(),
+        KeyCode::Down | KeyCode::Tab => app.search_next(),
+        KeyCode::Char('n') if control => app.search_next(),
+        KeyCode::Up | KeyCode::BackTab => app.search_previous(),
+        KeyCode::Char('p') if control => app.search_previous(),
+        _ => {
+            if let Some(search) = app.search() {
+                match key.code {
+                    KeyCode::Left => search.query.left(),
+                    KeyCode::Right => search.query.right(),
+                    KeyCode::Home => search.query.home(),
+                    KeyCode::End => search.query.end(),
+                    KeyCode::Backspace => {
+                        search.query.backspace();
+                        search.selected = 0;
+                    }
+                    KeyCode::Delete => {
+                        search.query.delete();
+                        search.selected = 0;
+                    }
+                    KeyCode::Char(c) => {
+                        search.query.insert(c);
+                        search.selected = 0;
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_error_log_key(app: &mut App, key: KeyEvent) -> anyhow::Result<()> {
+    match key.code {
+        KeyCode::Esc | KeyCode::Char('q') => app.cancel(),
+        KeyCode::Char('j') | KeyCode::Down => app.scroll_error_log(true),
+        KeyCode::Char('k') | KeyCode::Up => app.scroll_error_log(false),
+        _ => {}
+    }
+
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use std::{
+    io,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crossterm::{
+    cursor::Show,
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use tokio::sync::mpsc;
+
+/// Puts the terminal into raw mode on the alternate screen, and back when dropped.
+///
+/// A panic hook restores the terminal too, so the panic message is readable and the shell usable.
+/// A panic in a background task leaves the event loop running, it checks `panicked` to quit.
+pub struct Guard;
+
+static PANICKED: AtomicBool = AtomicBool::new(false);
+
+/// Whether any thread or task panicked since the terminal was set up.
+pub fn panicked() -> bool {
+    PANICKED.load(Ordering::SeqCst)
+}
+
+impl Guard {
+    pub fn new() -> io::Result<Self> {
+        let hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            PANICKED.store(true, Ordering::SeqCst);
+            let _ = restore();
+            hook(info);
+        }));
+
+        enable_raw_mode()?;
+        if let Err(err) = execute!(io::stdout(), EnterAlternateScreen) {
+            let _ = restore();
+            return Err(err);
+        }
+
+        Ok(Self)
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let _ = restore();
+    }
+}
+
+fn restore() -> io::Result<()> {
+    disable_raw_mode()?;
+    execute!(io::stdout(), LeaveAlternateScreen, Show)
+}
+
+/// Receives once SIGINT or SIGTERM arrives, raw mode turns ctrl-c into a key so these only come
+/// from outside, e.g. `kill`.
+pub fn signals() -> mpsc::UnboundedReceiver<()> {
+    let (tx, rx) = mpsc::unbounded_channel();
+
+    let interrupt = tx.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            let _ = interrupt.send(());
+        }
+    });
+    #[cfg(unix)]
+    tokio::spawn(async move {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            if terminate.recv().await.is_some() {
+                let _ = tx.send(());
+            }
+        }
+    });
+
+    rx
+}
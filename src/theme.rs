@@ -0,0 +1,200 @@
+use tui::style::{Color, Modifier, Style};
+
+use crate::config::{StyleCfg, StyleName, ThemeCfg, ThemePreset};
+
+/// Styles of everything drawn by the tui.
+pub struct Theme {
+    pub text: Style,
+    pub tab: Style,
+    pub tab_selected: Style,
+    /// Tabs of the pinned views, on top of `tab`.
+    pub view_tab: Style,
+    /// Account names in the tabs bar.
+    pub account: Style,
+    pub header: Style,
+    /// Selected row of the tasks table and of the popups.
+    pub selected_row: Style,
+    pub overdue: Style,
+    pub due_today: Style,
+    pub upcoming: Style,
+    /// Completed tasks.
+    pub done: Style,
+    /// Hints and secondary text.
+    pub muted: Style,
+    pub border: Style,
+    pub border_focused: Style,
+    pub warning: Style,
+    /// Borders of popups asking about something that can not be undone.
+    pub danger: Style,
+    pub success: Style,
+}
+
+impl Theme {
+    /// Builds the theme from the preset with the configured styles on top, colors are dropped
+    /// when `NO_COLOR` is set.
+    pub fn new(cfg: &ThemeCfg) -> anyhow::Result<Self> {
+        let mut theme = match cfg.preset {
+            ThemePreset::Dark => Self::dark(),
+            ThemePreset::Light => Self::light(),
+        };
+        for (name, style) in &cfg.styles {
+            let patch =
+                parse_style(style).map_err(|err| anyhow::anyhow!("theme.{}: {}", name, err))?;
+            let style = theme.style_mut(name);
+            *style = style.patch(patch);
+        }
+
+        if std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+            theme.strip_colors();
+        }
+
+        Ok(theme)
+    }
+
+    fn dark() -> Self {
+        Self {
+            text: Style::default().fg(Color::White),
+            tab: Style::default().fg(Color::White),
+            tab_selected: Style::default().fg(Color::LightBlue),
+            view_tab: Style::default().add_modifier(Modifier::ITALIC),
+            account: Style::default()
+                .fg(Color::DarkGray)
+                .add_modifier(Modifier::BOLD),
+            header: Style::default().add_modifier(Modifier::BOLD),
+            selected_row: Style::default()
+                .bg(Color::DarkGray)
+                .add_modifier(Modifier::BOLD),
+            overdue: Style::default().fg(Color::Red),
+            due_today: Style::default().fg(Color::Yellow),
+            upcoming: Style::default().fg(Color::Green),
+            done: Style::default().fg(Color::DarkGray),
+            muted: Style::default().fg(Color::DarkGray),
+            border: Style::default().fg(Color::White),
+            border_focused: Style::default().fg(Color::LightBlue),
+            warning: Style::default().fg(Color::Yellow),
+            danger: Style::default().fg(Color::Red),
+            success: Style::default().fg(Color::Green),
+        }
+    }
+
+    fn light() -> Self {
+        Self {
+            text: Style::default().fg(Color::Black),
+            tab: Style::default().fg(Color::Black),
+            tab_selected: Style::default().fg(Color::Blue),
+            view_tab: Style::default().add_modifier(Modifier::ITALIC),
+            account: Style::default()
+                .fg(Color::DarkGray)
+                .add_modifier(Modifier::BOLD),
+            header: Style::default().add_modifier(Modifier::BOLD),
+            selected_row: Style::default()
+                .bg(Color::Gray)
+                .add_modifier(Modifier::BOLD),
+            overdue: Style::default().fg(Color::Red),
+            due_today: Style::default().fg(Color::Magenta),
+            upcoming: Style::default().fg(Color::Green),
+            done: Style::default().fg(Color::Gray),
+            muted: Style::default().fg(Color::DarkGray),
+            border: Style::default().fg(Color::Black),
+            border_focused: Style::default().fg(Color::Blue),
+            warning: Style::default().fg(Color::Magenta),
+            danger: Style::default().fg(Color::Red),
+            success: Style::default().fg(Color::Green),
+        }
+    }
+
+    fn style_mut(&mut self, name: &StyleName) -> &mut Style {
+        match name {
+            StyleName::Text => &mut self.text,
+            StyleName::Tab => &mut self.tab,
+            StyleName::TabSelected => &mut self.tab_selected,
+            StyleName::ViewTab => &mut self.view_tab,
+            StyleName::Account => &mut self.account,
+            StyleName::Header => &mut self.header,
+            StyleName::SelectedRow => &mut self.selected_row,
+            StyleName::Overdue => &mut self.overdue,
+            StyleName::DueToday => &mut self.due_today,
+            StyleName::Upcoming => &mut self.upcoming,
+            StyleName::Done => &mut self.done,
+            StyleName::Muted => &mut self.muted,
+            StyleName::Border => &mut self.border,
+            StyleName::BorderFocused => &mut self.border_focused,
+            StyleName::Warning => &mut self.warning,
+            StyleName::Danger => &mut self.danger,
+            StyleName::Success => &mut self.success,
+        }
+    }
+
+    /// Drops all colors, styles that stood out by their background are reversed instead.
+    fn strip_colors(&mut self) {
+        for name in <StyleName as schematic::ConfigEnum>::variants() {
+            let style = self.style_mut(&name);
+            let reversed = style.bg.is_some();
+
+            *style = Style::default().add_modifier(style.add_modifier);
+            if reversed {
+                *style = style.add_modifier(Modifier::REVERSED);
+            }
+        }
+        self.tab_selected = self.tab_selected.add_modifier(Modifier::UNDERLINED);
+    }
+}
+
+fn parse_style(cfg: &StyleCfg) -> anyhow::Result<Style> {
+    let mut style = Style::default();
+    if let Some(fg) = &cfg.fg {
+        style = style.fg(parse_color(fg)?);
+    }
+    if let Some(bg) = &cfg.bg {
+        style = style.bg(parse_color(bg)?);
+    }
+    for modifier in &cfg.modifiers {
+        style = style.add_modifier(match modifier.as_str() {
+            "bold" => Modifier::BOLD,
+            "dim" => Modifier::DIM,
+            "italic" => Modifier::ITALIC,
+            "underlined" => Modifier::UNDERLINED,
+            "reversed" => Modifier::REVERSED,
+            "crossed-out" => Modifier::CROSSED_OUT,
+            _ => anyhow::bail!("unknown modifier `{}`", modifier),
+        });
+    }
+
+    Ok(style)
+}
+
+/// Parses a color name like `light-blue`, a `#rrggbb` hex color or a 0-255 palette index.
+fn parse_color(color: &str) -> anyhow::Result<Color> {
+    Ok(match color {
+        "reset" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" => Color::Gray,
+        "dark-gray" => Color::DarkGray,
+        "light-red" => Color::LightRed,
+        "light-green" => Color::LightGreen,
+        "light-yellow" => Color::LightYellow,
+        "light-blue" => Color::LightBlue,
+        "light-magenta" => Color::LightMagenta,
+        "light-cyan" => Color::LightCyan,
+        "white" => Color::White,
+        _ => {
+            if let Some(hex) = color.strip_prefix('#').filter(|hex| hex.len() == 6) {
+                let rgb = u32::from_str_radix(hex, 16)
+                    .map_err(|_| anyhow::anyhow!("invalid color `{}`", color))?;
+                Color::Rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+            } else {
+                Color::Indexed(
+                    color
+                        .parse()
+                        .map_err(|_| anyhow::anyhow!("unknown color `{}`", color))?,
+                )
+            }
+        }
+    })
+}
@@ -1,37 +1,129 @@
 use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum TimestampType {
     Date(NaiveDate),
     Time(NaiveTime),
     DateTime(NaiveDateTime),
 }
+impl TimestampType {
+    /// Parses `YYYY-MM-DD [HH:MM]`, `HH:MM`, `today`, `tomorrow` or `+N` days from today.
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim();
+        let today = chrono::Local::now().date_naive();
+
+        match input {
+            "today" => return Some(TimestampType::Date(today)),
+            "tomorrow" => return Some(TimestampType::Date(today.succ_opt()?)),
+            _ => {}
+        }
+        if let Some(days) = input.strip_prefix('+').and_then(|d| d.parse().ok()) {
+            return Some(TimestampType::Date(
+                today.checked_add_days(chrono::Days::new(days))?,
+            ));
+        }
+
+        NaiveDateTime::parse_from_str(input, "%Y-%m-%d %H:%M")
+            .map(TimestampType::DateTime)
+            .or_else(|_| NaiveDate::parse_from_str(input, "%Y-%m-%d").map(TimestampType::Date))
+            .or_else(|_| NaiveTime::parse_from_str(input, "%H:%M").map(TimestampType::Time))
+            .ok()
+    }
+
+    /// Day the timestamp falls on, a time without a date is taken as today.
+    pub fn date(&self) -> NaiveDate {
+        match self {
+            TimestampType::Date(date) => *date,
+            TimestampType::Time(_) => chrono::Local::now().date_naive(),
+            TimestampType::DateTime(datetime) => datetime.date(),
+        }
+    }
+
+    /// Whether the timestamp already passed, a date passes once the day is over.
+    pub fn is_past(&self) -> bool {
+        let now = chrono::Local::now().naive_local();
+
+        match self {
+            TimestampType::Date(date) => *date < now.date(),
+            TimestampType::Time(time) => *time < now.time(),
+            TimestampType::DateTime(datetime) => *datetime < now,
+        }
+    }
+
+    fn time(&self) -> Option<NaiveTime> {
+        match self {
+            TimestampType::Date(_) => None,
+            TimestampType::Time(time) => Some(*time),
+            TimestampType::DateTime(datetime) => Some(datetime.time()),
+        }
+    }
+}
+/// Ordered by day, a whole day comes before the times on it.
+impl Ord for TimestampType {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.date(), self.time()).cmp(&(other.date(), other.time()))
+    }
+}
+impl PartialOrd for TimestampType {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl PartialEq for TimestampType {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+impl Eq for TimestampType {}
+impl std::fmt::Display for TimestampType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimestampType::Date(date) => write!(f, "{}", date.format("%Y-%m-%d")),
+            TimestampType::Time(time) => write!(f, "{}", time.format("%H:%M")),
+            TimestampType::DateTime(datetime) => write!(f, "{}", datetime.format("%Y-%m-%d %H:%M")),
+        }
+    }
+}
 
 pub mod formatter {
-    use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
-    use tui::style::Color;
+    use chrono::{NaiveDate, NaiveDateTime};
 
     use super::TimestampType;
 
-    pub fn absolute(timestamp: &TimestampType) -> (String, Color) {
+    /// How close a timestamp is, picks the style it is shown with.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Urgency {
+        Overdue,
+        Today,
+        Upcoming,
+    }
+
+    pub fn absolute(timestamp: &TimestampType) -> String {
         match timestamp {
-            TimestampType::Date(date) => (date.format("%F").to_string(), Color::White),
-            TimestampType::Time(time) => (time.format("%F").to_string(), Color::White),
-            TimestampType::DateTime(datetime) => (datetime.format("%F").to_string(), Color::White),
+            TimestampType::Date(date) => date.format("%F").to_string(),
+            TimestampType::Time(time) => time.format("%F").to_string(),
+            TimestampType::DateTime(datetime) => datetime.format("%F").to_string(),
         }
     }
 
-    pub fn relative(timestamp: &TimestampType) -> (String, Color) {
+    /// How far the timestamp is from now, on the local clock like the Today view.
+    pub fn relative(timestamp: &TimestampType) -> (String, Urgency) {
+        relative_at(timestamp, chrono::Local::now().naive_local())
+    }
+
+    /// Like `relative`, seen from the local time `now`.
+    pub fn relative_at(timestamp: &TimestampType, now: NaiveDateTime) -> (String, Urgency) {
         match timestamp {
-            TimestampType::Date(date) => relative_date(date),
-            TimestampType::Time(time) => relative_time(time),
-            TimestampType::DateTime(datetime) => relative_datetime(datetime),
+            TimestampType::Date(date) => relative_date(date, now.date()),
+            TimestampType::Time(time) => relative_minutes((*time - now.time()).num_minutes()),
+            TimestampType::DateTime(datetime) => relative_datetime(datetime, now),
         }
     }
 
-    fn relative_date(date: &NaiveDate) -> (String, Color) {
-        let now = chrono::Utc::now().naive_utc().date();
-        let diff = date.to_owned() - now;
+    fn relative_date(date: &NaiveDate, today: NaiveDate) -> (String, Urgency) {
+        let diff = date.to_owned() - today;
 
         let days = diff.num_days();
 
@@ -48,19 +140,14 @@ pub mod formatter {
                 14.. => format!("in {} weeks", (days / 7)),
             },
             match days {
-                ..=-1 => Color::Red,
-                0 => Color::Yellow,
-                0.. => Color::Green,
+                ..=-1 => Urgency::Overdue,
+                0 => Urgency::Today,
+                1.. => Urgency::Upcoming,
             },
         )
     }
 
-    fn relative_time(time: &NaiveTime) -> (String, Color) {
-        let now = chrono::Utc::now().naive_utc().time();
-        let diff = time.to_owned() - now;
-
-        let minutes = diff.num_minutes();
-
+    fn relative_minutes(minutes: i64) -> (String, Urgency) {
         (
             match minutes {
                 ..=-120 => format!("{} hours ago", (minutes / 60).abs()),
@@ -74,23 +161,21 @@ pub mod formatter {
                 120.. => format!("in {} hours", (minutes / 60)),
             },
             match minutes {
-                ..=-1 => Color::Red,
-                0 => Color::Yellow,
-                0.. => Color::Green,
+                ..=-1 => Urgency::Overdue,
+                0 => Urgency::Today,
+                1.. => Urgency::Upcoming,
             },
         )
     }
 
-    fn relative_datetime(datetime: &NaiveDateTime) -> (String, Color) {
-        let now = chrono::Utc::now().naive_utc();
+    /// Within a day the distance is counted in hours and minutes, also across midnight.
+    fn relative_datetime(datetime: &NaiveDateTime, now: NaiveDateTime) -> (String, Urgency) {
         let diff = datetime.to_owned() - now;
 
-        let days = diff.num_days();
-
-        if days != 0 {
-            relative_date(&(datetime.date()))
+        if diff.num_days() != 0 {
+            relative_date(&datetime.date(), now.date())
         } else {
-            relative_time(&(datetime.time()))
+            relative_minutes(diff.num_minutes())
         }
     }
 }
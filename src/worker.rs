@@ -0,0 +1,719 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use tokio::sync::mpsc;
+
+use crate::{
+    app::{place, remove_subtree, Task, Tasklist},
+    provider::Provider,
+};
+
+/// A provider call made from the tui, tasks and tasklists it creates get a temporary id that
+/// later jobs can already use.
+#[derive(Clone, Debug)]
+pub enum Job {
+    Sync,
+    CreateTasklist {
+        id: String,
+        account: Option<String>,
+        title: String,
+    },
+    RenameTasklist {
+        tasklist_id: String,
+        title: String,
+    },
+    DeleteTasklist {
+        tasklist_id: String,
+        title: String,
+    },
+    UpdateTask {
+        tasklist_id: String,
+        task: Task,
+    },
+    CreateTask {
+        tasklist_id: String,
+        task: Task,
+    },
+    DeleteTask {
+        tasklist_id: String,
+        task: Task,
+    },
+    MoveTask {
+        tasklist_id: String,
+        task: Task,
+        parent: Option<String>,
+        previous: Option<String>,
+    },
+    MoveTaskToList {
+        from: String,
+        task: Task,
+        to: String,
+    },
+}
+impl Job {
+    /// Task the job changes, marked as pending in the table until it is done.
+    fn task_id(&self) -> Option<&str> {
+        match self {
+            Job::UpdateTask { task, .. }
+            | Job::CreateTask { task, .. }
+            | Job::DeleteTask { task, .. }
+            | Job::MoveTask { task, .. }
+            | Job::MoveTaskToList { task, .. } => Some(&task.id),
+            _ => None,
+        }
+    }
+
+    /// Temporary id of the task or tasklist the job creates.
+    fn new_id(&self) -> Option<&str> {
+        match self {
+            Job::CreateTasklist { id, .. } => Some(id),
+            Job::CreateTask { task, .. } => Some(&task.id),
+            _ => None,
+        }
+    }
+
+    /// Makes the change of the job to the tasklists shown while the provider is still at it,
+    /// `ids` has the real ids of what earlier jobs created.
+    fn apply(&self, tasklists: &mut Vec<Tasklist>, ids: &HashMap<String, String>) {
+        let real = |id: &str| ids.get(id).cloned().unwrap_or_else(|| id.to_string());
+
+        match self {
+            Job::Sync => {}
+            Job::CreateTasklist { id, title, .. } => {
+                if !tasklists.iter().any(|t| &t.id == id) {
+                    tasklists.push(Tasklist::new(id.clone(), title.clone(), &[]));
+                }
+            }
+            Job::RenameTasklist { tasklist_id, title } => {
+                if let Some(tasklist) = tasklists.iter_mut().find(|t| t.id == real(tasklist_id)) {
+                    tasklist.title = title.clone();
+                }
+            }
+            Job::DeleteTasklist { tasklist_id, .. } => {
+                tasklists.retain(|t| t.id != real(tasklist_id));
+            }
+            Job::UpdateTask { tasklist_id, task } => {
+                if let Some(t) = tasks_mut(tasklists, &real(tasklist_id))
+                    .and_then(|tasks| tasks.iter_mut().find(|t| t.id == real(&task.id)))
+                {
+                    *t = Task {
+                        id: t.id.clone(),
+                        parent: t.parent.clone(),
+                        position: t.position.clone(),
+                        ..task.clone()
+                    };
+                }
+            }
+            // Providers put new tasks first among their siblings.
+            Job::CreateTask { tasklist_id, task } => {
+                if let Some(tasks) = tasks_mut(tasklists, &real(tasklist_id)) {
+                    if !tasks.iter().any(|t| t.id == task.id) {
+                        let parent = task.parent.as_deref().map(real);
+                        tasks.push(Task {
+                            parent: parent.clone(),
+                            ..task.clone()
+                        });
+                        place(tasks, &task.id, parent.as_deref(), None);
+                    }
+                }
+            }
+            Job::DeleteTask { tasklist_id, task } => {
+                if let Some(tasks) = tasks_mut(tasklists, &real(tasklist_id)) {
+                    remove_subtree(tasks, &real(&task.id));
+                }
+            }
+            Job::MoveTask {
+                tasklist_id,
+                task,
+                parent,
+                previous,
+            } => {
+                if let Some(tasks) = tasks_mut(tasklists, &real(tasklist_id)) {
+                    place(
+                        tasks,
+                        &real(&task.id),
+                        parent.as_deref().map(real).as_deref(),
+                        previous.as_deref().map(real).as_deref(),
+                    );
+                }
+            }
+            Job::MoveTaskToList { from, task, to } => {
+                let moved = tasks_mut(tasklists, &real(from))
+                    .map(|tasks| remove_subtree(tasks, &real(&task.id)))
+                    .unwrap_or_default();
+                if let Some(tasks) = tasks_mut(tasklists, &real(to)) {
+                    tasks.extend(moved);
+                }
+            }
+        }
+    }
+}
+
+fn tasks_mut<'a>(tasklists: &'a mut [Tasklist], tasklist_id: &str) -> Option<&'a mut Vec<Task>> {
+    tasklists
+        .iter_mut()
+        .find(|t| t.id == tasklist_id)
+        .map(|t| &mut t.tasks)
+}
+
+impl fmt::Display for Job {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Job::Sync => write!(f, "sync"),
+            Job::CreateTasklist { title, .. } => write!(f, "create tasklist \"{}\"", title),
+            Job::RenameTasklist { title, .. } => write!(f, "rename tasklist to \"{}\"", title),
+            Job::DeleteTasklist { title, .. } => write!(f, "delete tasklist \"{}\"", title),
+            Job::UpdateTask { task, .. } => write!(f, "update task \"{}\"", task.title),
+            Job::CreateTask { task, .. } => write!(f, "create task \"{}\"", task.title),
+            Job::DeleteTask { task, .. } => write!(f, "delete task \"{}\"", task.title),
+            Job::MoveTask { task, .. } | Job::MoveTaskToList { task, .. } => {
+                write!(f, "move task \"{}\"", task.title)
+            }
+        }
+    }
+}
+
+enum Message {
+    Job(u64, Box<Job>),
+    /// Tasklists fetched by the loader or watcher, with the generation they were fetched at.
+    Refresh(u64, anyhow::Result<Vec<Tasklist>>),
+}
+
+/// State of the provider after a job, as the tui can not ask it directly.
+#[derive(Default)]
+struct Snapshot {
+    tasklists: Vec<Tasklist>,
+    accounts: HashMap<String, String>,
+    pending_changes: usize,
+}
+impl Snapshot {
+    fn of(provider: &dyn Provider) -> Self {
+        let tasklists = provider.get_tasklists().clone();
+        let accounts = tasklists
+            .iter()
+            .filter_map(|t| Some((t.id.clone(), provider.account(&t.id)?.to_string())))
+            .collect();
+
+        Self {
+            tasklists,
+            accounts,
+            pending_changes: provider.pending_changes(),
+        }
+    }
+}
+
+/// Result of a job, or of a refresh when `id` is `None`.
+pub struct Done {
+    pub id: Option<u64>,
+    pub result: anyhow::Result<()>,
+    /// Whether the provider could not be reached.
+    pub offline: bool,
+    /// Real id of the task or tasklist the job created.
+    created: Option<String>,
+    snapshot: Snapshot,
+}
+
+/// Runs the provider in the background, one job at a time, and keeps a copy of its tasklists
+/// with the changes of the jobs that are not done yet.
+pub struct Worker {
+    tasklists: Vec<Tasklist>,
+    accounts: HashMap<String, String>,
+    pending_changes: usize,
+
+    next_id: u64,
+    running: BTreeMap<u64, Job>,
+    /// Real ids of the tasks and tasklists created in the background, by temporary id.
+    ids: HashMap<String, String>,
+    messages: mpsc::UnboundedSender<Message>,
+    /// Number of jobs the provider ran, tasklists fetched before the last one may miss its change.
+    generation: Arc<AtomicU64>,
+}
+
+impl Worker {
+    pub fn spawn(mut provider: Box<dyn Provider>) -> (Self, mpsc::UnboundedReceiver<Done>) {
+        let Snapshot {
+            tasklists,
+            accounts,
+            pending_changes,
+        } = Snapshot::of(provider.as_ref());
+
+        let (messages, mut rx) = mpsc::unbounded_channel();
+        let (tx, done) = mpsc::unbounded_channel();
+        let generation = Arc::new(AtomicU64::new(0));
+        let jobs = generation.clone();
+        tokio::spawn(async move {
+            let mut ids = HashMap::new();
+            let mut etags = HashMap::new();
+            while let Some(message) = rx.recv().await {
+                let (id, result) = match message {
+                    Message::Job(id, job) => {
+                        let result = run(provider.as_mut(), &ids, &mut etags, &job).await;
+                        if let (Ok(Some(created)), Some(new_id)) = (&result, job.new_id()) {
+                            ids.insert(new_id.to_string(), created.clone());
+                        }
+                        jobs.fetch_add(1, Ordering::SeqCst);
+
+                        (Some(id), result)
+                    }
+                    Message::Refresh(started, _) if started < jobs.load(Ordering::SeqCst) => {
+                        continue
+                    }
+                    Message::Refresh(_, tasklists) => (
+                        None,
+                        tasklists.map(|tasklists| {
+                            provider.set_tasklists(tasklists);
+                            None
+                        }),
+                    ),
+                };
+
+                let offline = result
+                    .as_ref()
+                    .is_err_and(|err| provider.is_offline_error(err));
+                let (result, created) = match result {
+                    Ok(created) => (Ok(()), created),
+                    Err(err) => (Err(err), None),
+                };
+                let done = Done {
+                    id,
+                    result,
+                    offline,
+                    created,
+                    snapshot: Snapshot::of(provider.as_ref()),
+                };
+                if tx.send(done).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let worker = Self {
+            tasklists,
+            accounts,
+            pending_changes,
+            next_id: 0,
+            running: BTreeMap::new(),
+            ids: HashMap::new(),
+            messages,
+            generation,
+        };
+
+        (worker, done)
+    }
+
+    pub fn get_tasklists(&self) -> &Vec<Tasklist> {
+        &self.tasklists
+    }
+    pub fn len(&self) -> usize {
+        self.tasklists.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.tasklists.is_empty()
+    }
+    pub fn get_tasklist(&self, tasklist_id: &str) -> Option<&Tasklist> {
+        self.tasklists.iter().find(|t| t.id == tasklist_id)
+    }
+    pub fn get_nth_tasklist(&self, n: usize) -> Option<&Tasklist> {
+        self.tasklists.get(n)
+    }
+    /// Name of the account the tasklist belongs to, when tasklists of several are shown.
+    pub fn account(&self, tasklist_id: &str) -> Option<&str> {
+        self.accounts.get(tasklist_id).map(String::as_str)
+    }
+    /// Number of changes made offline that still wait for a successful sync.
+    pub fn pending_changes(&self) -> usize {
+        self.pending_changes
+    }
+
+    /// Temporary id for a task or tasklist created by a job.
+    pub fn new_id(&mut self) -> String {
+        self.next_id += 1;
+        format!("new-{}", self.next_id)
+    }
+    /// Real id of a task or tasklist created in the background, other ids are returned as they are.
+    pub fn resolve(&self, id: &str) -> String {
+        self.ids.get(id).cloned().unwrap_or_else(|| id.to_string())
+    }
+
+    /// Queues the job and shows its change right away, returns the id of the job.
+    pub fn send(&mut self, job: Job) -> anyhow::Result<u64> {
+        self.next_id += 1;
+        self.messages
+            .send(Message::Job(self.next_id, Box::new(job.clone())))
+            .map_err(|_| anyhow::anyhow!("the provider stopped, restart tudo"))?;
+        job.apply(&mut self.tasklists, &self.ids);
+        self.running.insert(self.next_id, job);
+
+        Ok(self.next_id)
+    }
+    /// Hands fetched tasklists to the provider, unless a job ran since `started`.
+    pub fn refresh(&mut self, started: u64, tasklists: anyhow::Result<Vec<Tasklist>>) {
+        let _ = self.messages.send(Message::Refresh(started, tasklists));
+    }
+    /// Counter to read right before fetching the tasklists passed to `refresh`.
+    pub fn generation(&self) -> Arc<AtomicU64> {
+        self.generation.clone()
+    }
+
+    /// Number of jobs that are not done yet.
+    pub fn running(&self) -> usize {
+        self.running.len()
+    }
+    pub fn is_running(&self, task_id: &str) -> bool {
+        self.running
+            .values()
+            .any(|job| job.task_id() == Some(task_id))
+    }
+
+    /// Takes back the job that is done.
+    ///
+    /// The tasklists of the provider replace the copy, with the changes of the jobs still
+    /// running made again on top, so a failed job only rolls back its own change.
+    pub fn finish(&mut self, done: &mut Done) -> Option<Job> {
+        let job = done.id.and_then(|id| self.running.remove(&id));
+        if let (Some(created), Some(new_id)) = (&done.created, job.as_ref().and_then(Job::new_id)) {
+            self.ids.insert(new_id.to_string(), created.clone());
+        }
+
+        let snapshot = std::mem::take(&mut done.snapshot);
+        self.tasklists = snapshot.tasklists;
+        for job in self.running.values() {
+            job.apply(&mut self.tasklists, &self.ids);
+        }
+        self.accounts = snapshot.accounts;
+        self.pending_changes = snapshot.pending_changes;
+
+        job
+    }
+}
+
+/// Runs the job with the real ids of what earlier jobs created, returning the id of what it
+/// created itself.
+///
+/// `etags` maps the etag each finished update was sent with to the one it got back, by task id.
+/// Updates queued before an earlier one finished carry the etag it replaced and get the new one.
+async fn run(
+    provider: &mut dyn Provider,
+    ids: &HashMap<String, String>,
+    etags: &mut HashMap<(String, String), String>,
+    job: &Job,
+) -> anyhow::Result<Option<String>> {
+    let real = |id: &str| ids.get(id).cloned().unwrap_or_else(|| id.to_string());
+    let real_task = |task: &Task| Task {
+        id: real(&task.id),
+        parent: task.parent.as_deref().map(real),
+        ..task.clone()
+    };
+
+    match job {
+        Job::Sync => provider.sync().await?,
+        Job::CreateTasklist { account, title, .. } => {
+            return Ok(Some(
+                provider
+                    .create_tasklist_in(account.as_deref(), title)
+                    .await?,
+            ));
+        }
+        Job::RenameTasklist { tasklist_id, title } => {
+            provider.rename_tasklist(&real(tasklist_id), title).await?
+        }
+        Job::DeleteTasklist { tasklist_id, .. } => {
+            provider.delete_tasklist(&real(tasklist_id)).await?
+        }
+        Job::UpdateTask { tasklist_id, task } => {
+            let tasklist_id = real(tasklist_id);
+            let mut task = real_task(task);
+            for _ in 0..etags.len() {
+                match task
+                    .etag
+                    .as_ref()
+                    .and_then(|etag| etags.get(&(task.id.clone(), etag.clone())))
+                {
+                    Some(etag) => task.etag = Some(etag.clone()),
+                    None => break,
+                }
+            }
+
+            provider.update_task(&tasklist_id, &task).await?;
+
+            let updated = provider
+                .get_task(&tasklist_id, &task.id)
+                .and_then(|t| t.etag.clone());
+            if let (Some(sent), Some(updated)) = (task.etag.clone(), updated) {
+                if sent != updated {
+                    etags.insert((task.id, sent), updated);
+                }
+            }
+        }
+        Job::CreateTask { tasklist_id, task } => {
+            return Ok(Some(
+                provider
+                    .create_task(&real(tasklist_id), &real_task(task))
+                    .await?,
+            ));
+        }
+        Job::DeleteTask { tasklist_id, task } => {
+            provider
+                .delete_task(&real(tasklist_id), &real(&task.id))
+                .await?
+        }
+        Job::MoveTask {
+            tasklist_id,
+            task,
+            parent,
+            previous,
+        } => {
+            provider
+                .move_task(
+                    &real(tasklist_id),
+                    &real(&task.id),
+                    parent.as_deref().map(real).as_deref(),
+                    previous.as_deref().map(real).as_deref(),
+                )
+                .await?
+        }
+        Job::MoveTaskToList { from, task, to } => {
+            provider
+                .move_task_to_list(&real(from), &real(&task.id), &real(to))
+                .await?
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::Status;
+
+    fn worker(tasklists: Vec<Tasklist>) -> (Worker, mpsc::UnboundedReceiver<Message>) {
+        let (messages, rx) = mpsc::unbounded_channel();
+        let worker = Worker {
+            tasklists,
+            accounts: HashMap::new(),
+            pending_changes: 0,
+            next_id: 0,
+            running: BTreeMap::new(),
+            ids: HashMap::new(),
+            messages,
+            generation: Arc::default(),
+        };
+
+        (worker, rx)
+    }
+
+    fn done(id: u64, result: anyhow::Result<()>, tasklists: Vec<Tasklist>) -> Done {
+        Done {
+            id: Some(id),
+            result,
+            offline: false,
+            created: None,
+            snapshot: Snapshot {
+                tasklists,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn titles(worker: &Worker) -> Vec<String> {
+        let mut tasks = worker.get_tasklists()[0].tasks.clone();
+        tasks.sort_by(|a, b| a.position.cmp(&b.position));
+        tasks.into_iter().map(|t| t.title).collect()
+    }
+
+    #[test]
+    fn failed_job_only_rolls_back_its_own_change() {
+        let task = Task::new("a", Status::Todo, "Task", None, None);
+        let provider = vec![Tasklist::new(
+            "l".to_string(),
+            "List".to_string(),
+            std::slice::from_ref(&task),
+        )];
+        let (mut worker, _rx) = worker(provider.clone());
+
+        let update = worker
+            .send(Job::UpdateTask {
+                tasklist_id: "l".to_string(),
+                task: Task {
+                    title: "Renamed".to_string(),
+                    ..task.clone()
+                },
+            })
+            .unwrap();
+        let id = worker.new_id();
+        worker
+            .send(Job::CreateTask {
+                tasklist_id: "l".to_string(),
+                task: Task::new(&id, Status::Todo, "New", None, None),
+            })
+            .unwrap();
+        assert_eq!(titles(&worker), ["New", "Renamed"]);
+
+        worker.finish(&mut done(
+            update,
+            Err(anyhow::anyhow!("rejected")),
+            provider,
+        ));
+
+        assert_eq!(titles(&worker), ["New", "Task"]);
+        assert!(worker.is_running(&id));
+    }
+
+    #[test]
+    fn later_jobs_use_the_real_ids() {
+        let provider = vec![Tasklist::new("l".to_string(), "List".to_string(), &[])];
+        let (mut worker, _rx) = worker(provider);
+
+        let id = worker.new_id();
+        let task = Task::new(&id, Status::Todo, "New", None, None);
+        let create = worker
+            .send(Job::CreateTask {
+                tasklist_id: "l".to_string(),
+                task: task.clone(),
+            })
+            .unwrap();
+        worker
+            .send(Job::UpdateTask {
+                tasklist_id: "l".to_string(),
+                task: Task {
+                    status: Status::Done,
+                    ..task
+                },
+            })
+            .unwrap();
+
+        let created = Task::new("real", Status::Todo, "New", None, None);
+        let mut done = done(
+            create,
+            Ok(()),
+            vec![Tasklist::new(
+                "l".to_string(),
+                "List".to_string(),
+                &[created],
+            )],
+        );
+        done.created = Some("real".to_string());
+        worker.finish(&mut done);
+
+        let tasks = &worker.get_tasklists()[0].tasks;
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].id, "real");
+        assert!(matches!(tasks[0].status, Status::Done));
+        assert_eq!(worker.resolve(&id), "real");
+    }
+
+    /// Provider that bumps the etag of every updated task and rejects stale ones.
+    struct Versioned {
+        tasklists: Vec<Tasklist>,
+        updates: usize,
+    }
+
+    #[async_trait::async_trait]
+    impl Provider for Versioned {
+        fn get_tasklists(&self) -> &Vec<Tasklist> {
+            &self.tasklists
+        }
+        async fn sync(&mut self) -> anyhow::Result<()> {
+            Ok(())
+        }
+        async fn create_tasklist(&mut self, _: &str) -> anyhow::Result<String> {
+            anyhow::bail!("not supported")
+        }
+        async fn rename_tasklist(&mut self, _: &str, _: &str) -> anyhow::Result<()> {
+            anyhow::bail!("not supported")
+        }
+        async fn delete_tasklist(&mut self, _: &str) -> anyhow::Result<()> {
+            anyhow::bail!("not supported")
+        }
+        async fn update_task(&mut self, tasklist_id: &str, task: &Task) -> anyhow::Result<()> {
+            self.updates += 1;
+            let etag = format!("v{}", self.updates);
+            let remote = tasks_mut(&mut self.tasklists, tasklist_id)
+                .and_then(|tasks| tasks.iter_mut().find(|t| t.id == task.id))
+                .ok_or(anyhow::anyhow!("task {} not found", task.id))?;
+            if remote.etag != task.etag {
+                return Err(crate::provider::Conflict {
+                    tasklist_id: tasklist_id.to_string(),
+                    local: task.clone(),
+                    remote: remote.clone(),
+                }
+                .into());
+            }
+            *remote = Task {
+                etag: Some(etag),
+                ..task.clone()
+            };
+            Ok(())
+        }
+        async fn create_task(&mut self, _: &str, _: &Task) -> anyhow::Result<String> {
+            anyhow::bail!("not supported")
+        }
+        async fn delete_task(&mut self, _: &str, _: &str) -> anyhow::Result<()> {
+            anyhow::bail!("not supported")
+        }
+        async fn move_task(
+            &mut self,
+            _: &str,
+            _: &str,
+            _: Option<&str>,
+            _: Option<&str>,
+        ) -> anyhow::Result<()> {
+            anyhow::bail!("not supported")
+        }
+    }
+
+    #[test]
+    fn queued_updates_of_a_task_use_the_etag_of_the_previous_one() {
+        let task = Task {
+            etag: Some("v0".to_string()),
+            ..Task::new("a", Status::Todo, "Task", None, None)
+        };
+        let provider = Versioned {
+            tasklists: vec![Tasklist::new(
+                "l".to_string(),
+                "List".to_string(),
+                std::slice::from_ref(&task),
+            )],
+            updates: 0,
+        };
+
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let (mut worker, mut done) = Worker::spawn(Box::new(provider));
+                // Both are sent before the worker task gets to run, so both carry "v0".
+                for title in ["One", "Two"] {
+                    worker
+                        .send(Job::UpdateTask {
+                            tasklist_id: "l".to_string(),
+                            task: Task {
+                                title: title.to_string(),
+                                ..task.clone()
+                            },
+                        })
+                        .unwrap();
+                }
+
+                for _ in 0..2 {
+                    let mut done = done.recv().await.unwrap();
+                    if let Err(err) = &done.result {
+                        panic!("update failed: {}", err);
+                    }
+                    worker.finish(&mut done);
+                }
+                assert_eq!(titles(&worker), ["Two"]);
+                assert_eq!(
+                    worker.get_tasklists()[0].tasks[0].etag.as_deref(),
+                    Some("v2")
+                );
+            });
+    }
+}